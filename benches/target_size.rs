@@ -0,0 +1,33 @@
+//! Compares the encoded size of typical targets across encodings.
+//!
+//! Run with `cargo bench --bench target_size --features json,msgpack,cbor`.
+
+use wire::{codec::*, AuthTarget, Compact, Target, Targets, UserPool};
+
+/// The regular serde encoding of the targets, bypassing the compact encoding of the envelopes.
+#[derive(serde::Serialize)]
+struct Plain<'a>(&'a Targets);
+
+fn main() {
+	let mut pool = UserPool::default();
+	let specific = pool.next_auth();
+	let all = Target::Auth(AuthTarget::All(specific.user_id().unwrap()));
+	let room = (0..8).map(|_| pool.next_auth()).collect::<Vec<_>>();
+	let cases = [
+		("all", Targets::All),
+		("anon", pool.next_anon().into()),
+		("auth specific", specific.into()),
+		("auth all", all.into()),
+		("room of 8", room.clone().into()),
+		("room of 8 except 1", Targets::Few(room.clone()) - room[0]),
+	];
+
+	println!("{:<20} {:>8} {:>8} {:>8} {:>8}", "targets", "json", "msgpack", "cbor", "compact");
+	for (name, targets) in cases {
+		let json = Json.encode(&Plain(&targets)).unwrap().len();
+		let msgpack = MsgPack.encode(&Plain(&targets)).unwrap().len();
+		let cbor = Cbor.encode(&Plain(&targets)).unwrap().len();
+		let compact = targets.to_compact().len();
+		println!("{name:<20} {json:>8} {msgpack:>8} {cbor:>8} {compact:>8}");
+	}
+}
@@ -8,3 +8,5 @@ session-err-unauth = You are not authenticated
 network-err-rate_limited = You have been rate-limited
 network-err-invalid_msg = An invalid message has been received
 network-err-socket_error = A socket error has occured: {$what}
+network-err-version_mismatch = No mutually supported protocol version exists
+network-err-timed_out = The request has timed out
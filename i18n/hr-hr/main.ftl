@@ -8,3 +8,5 @@ session-err-unauth = Niste ulogirani
 network-err-rate_limited = Vaše radnje su ograničene brzinom
 network-err-invalid_msg = Poruka nije validna
 network-err-socket_error = Greška na socketu: {$what}
+network-err-version_mismatch = Ne postoji zajednički podržana verzija protokola
+network-err-timed_out = Zahtjev je istekao
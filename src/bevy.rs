@@ -0,0 +1,268 @@
+//! Integration with [`bevy`].
+//!
+//! [`WirePlugin`] turns incoming [`Req<A>`] into per-variant `Req<Variant>` events
+//! and collects outgoing [`Res<E>`] and [`Error<Err>`] events into an [`Outbox`]
+//! for the transport layer to send.
+//!
+//! Lifecycle events ([`Connected`], [`Disconnected`], [`FirstConnected`], [`LastDisconnected`], [`Resumed`]) are bevy events as well.
+//! The plugin registers them for the [`Undetermined`] marker, along with the [`GroupJoined`] and [`GroupLeft`] events.
+//!
+//! [`bevy`]: https://bevyengine.org
+
+use bevy_app::{App, Plugin, PostUpdate, PreUpdate};
+use bevy_ecs::{
+	event::{Event, Events},
+	resource::Resource,
+	system::ResMut,
+	world::World,
+};
+
+use crate::*;
+
+/// An action enum whose variants can be dispatched as separate bevy events.
+///
+/// Implemented automatically by [`WireObj`] for enums marked with `#[wire(bevy)]`.
+pub trait WireEvents: Sized + Send + Sync + 'static {
+	/// Registers the `Req<Variant>` event of every variant.
+	fn add_events(app: &mut App);
+
+	/// Sends the request as the `Req<Variant>` event of its variant.
+	///
+	/// Requests of variants skipped with `#[wire(skip)]` are sent as `Req<Self>` instead.
+	fn send_event(req: Req<Self>, world: &mut World);
+}
+
+/// A queue of incoming requests, filled by the transport layer.
+///
+/// Drained every frame in [`PreUpdate`] and dispatched as per-variant events.
+#[derive(Resource)]
+pub struct Inbox<A> {
+	/// The received requests.
+	pub reqs: Vec<Req<A>>,
+}
+
+impl<A> Inbox<A> {
+	/// Queues a received request.
+	pub fn push(&mut self, req: Req<A>) {
+		self.reqs.push(req);
+	}
+}
+
+impl<A> Default for Inbox<A> {
+	fn default() -> Self {
+		Self { reqs: Vec::new() }
+	}
+}
+
+/// A queue of outgoing responses and errors, drained by the transport layer.
+///
+/// Collected every frame in [`PostUpdate`] from the `Res<E>` and `Error<Err>` events.
+#[derive(Resource)]
+pub struct Outbox<E, Err> {
+	/// The responses to send.
+	pub responses: Vec<Res<E>>,
+	/// The errors to send.
+	pub errors: Vec<Error<Err>>,
+}
+
+impl<E, Err> Outbox<E, Err> {
+	/// Takes all queued responses.
+	pub fn take_responses(&mut self) -> Vec<Res<E>> {
+		std::mem::take(&mut self.responses)
+	}
+
+	/// Takes all queued errors.
+	pub fn take_errors(&mut self) -> Vec<Error<Err>> {
+		std::mem::take(&mut self.errors)
+	}
+}
+
+impl<E, Err> Default for Outbox<E, Err> {
+	fn default() -> Self {
+		Self {
+			responses: Vec::new(),
+			errors: Vec::new(),
+		}
+	}
+}
+
+/// A plugin wiring an action-event-error protocol into bevy.
+///
+/// Requires the action enum to derive [`WireObj`].
+pub struct WirePlugin<A, E, Err> {
+	_phantom: std::marker::PhantomData<(A, E, Err)>,
+}
+
+impl<A, E, Err> WirePlugin<A, E, Err> {
+	/// Creates a new [`WirePlugin`].
+	pub fn new() -> Self {
+		Self { _phantom: Default::default() }
+	}
+}
+
+impl<A, E, Err> Default for WirePlugin<A, E, Err> {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl<A, E, Err> Plugin for WirePlugin<A, E, Err>
+where
+	A: WireEvents,
+	E: Send + Sync + 'static,
+	Err: Send + Sync + 'static,
+{
+	fn build(&self, app: &mut App) {
+		A::add_events(app);
+		app.add_event::<Req<A>>()
+			.add_event::<Res<E>>()
+			.add_event::<Error<Err>>()
+			.add_event::<Connected<Undetermined>>()
+			.add_event::<FirstConnected<Undetermined>>()
+			.add_event::<Disconnected<Undetermined>>()
+			.add_event::<LastDisconnected<Undetermined>>()
+			.add_event::<Resumed<Undetermined>>()
+			.add_event::<GroupJoined>()
+			.add_event::<GroupLeft>()
+			.init_resource::<Inbox<A>>()
+			.init_resource::<Outbox<E, Err>>()
+			.add_systems(PreUpdate, dispatch_incoming::<A>)
+			.add_systems(PostUpdate, collect_outgoing::<E, Err>);
+	}
+}
+
+/// Dispatches the queued incoming requests as per-variant events.
+fn dispatch_incoming<A>(world: &mut World)
+where
+	A: WireEvents,
+{
+	let reqs = std::mem::take(&mut world.resource_mut::<Inbox<A>>().reqs);
+	for req in reqs {
+		A::send_event(req, world);
+	}
+}
+
+/// Collects the outgoing responses and errors into the outbox.
+fn collect_outgoing<E, Err>(mut responses: ResMut<Events<Res<E>>>, mut errors: ResMut<Events<Error<Err>>>, mut outbox: ResMut<Outbox<E, Err>>)
+where
+	E: Send + Sync + 'static,
+	Err: Send + Sync + 'static,
+{
+	outbox.responses.extend(responses.drain());
+	outbox.errors.extend(errors.drain());
+}
+
+impl<A> Event for Req<A>
+where
+	A: Send + Sync + 'static,
+{
+	type Traversal = ();
+}
+
+impl<E> Event for Res<E>
+where
+	E: Send + Sync + 'static,
+{
+	type Traversal = ();
+}
+
+impl<E> Event for Error<E>
+where
+	E: Send + Sync + 'static,
+{
+	type Traversal = ();
+}
+
+impl<M> Event for Connected<M>
+where
+	M: Send + Sync + 'static,
+{
+	type Traversal = ();
+}
+
+impl<M> Event for FirstConnected<M>
+where
+	M: Send + Sync + 'static,
+{
+	type Traversal = ();
+}
+
+impl<M> Event for Disconnected<M>
+where
+	M: Send + Sync + 'static,
+{
+	type Traversal = ();
+}
+
+impl<M> Event for LastDisconnected<M>
+where
+	M: Send + Sync + 'static,
+{
+	type Traversal = ();
+}
+
+impl<M> Event for Resumed<M>
+where
+	M: Send + Sync + 'static,
+{
+	type Traversal = ();
+}
+
+impl Event for GroupJoined {
+	type Traversal = ();
+}
+
+impl Event for GroupLeft {
+	type Traversal = ();
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[derive(WireObj)]
+	#[rustfmt::ignore]
+	#[derive(Clone, Debug, PartialEq, Eq)]
+	#[wire(module = "actions", bevy)]
+	enum Action {
+		Move { x: i32, y: i32 },
+		#[wire(skip)]
+		Quit,
+	}
+
+	// not `Send`, so it must not get the bevy integration
+	#[derive(WireObj)]
+	#[rustfmt::ignore]
+	#[derive(Clone, Debug, PartialEq, Eq)]
+	#[wire(module = "local")]
+	enum Local {
+		Shared(std::rc::Rc<u32>),
+	}
+
+	#[test]
+	fn test_wire_plugin() {
+		let mut app = App::new();
+		app.add_plugins(WirePlugin::<Action, u32, NetworkError>::new());
+
+		let from = Target::new_anon(1);
+		let corrid = CorrelationId::new_v4();
+		let mut inbox = app.world_mut().resource_mut::<Inbox<Action>>();
+		inbox.push(Req::new(from, Action::Move { x: 1, y: 2 }, corrid));
+		inbox.push(Req::new(from, Action::Quit, corrid));
+		app.world_mut().send_event(Res::<u32>::new(from, 42u32));
+		app.world_mut().send_event(Error::<NetworkError>::new(from, NetworkError::RateLimited, corrid));
+		app.update();
+
+		let moves = app.world_mut().resource_mut::<Events<Req<actions::Move>>>().drain().collect::<Vec<_>>();
+		assert_eq!(moves, vec![Req::new(from, actions::Move { x: 1, y: 2 }, corrid)]);
+		let quits = app.world_mut().resource_mut::<Events<Req<Action>>>().drain().collect::<Vec<_>>();
+		assert_eq!(quits, vec![Req::new(from, Action::Quit, corrid)]);
+
+		let mut outbox = app.world_mut().resource_mut::<Outbox<u32, NetworkError>>();
+		assert_eq!(outbox.take_responses().len(), 1);
+		assert_eq!(outbox.take_errors(), vec![Error::new(from, NetworkError::RateLimited, corrid)]);
+
+		let shared = Local::from(local::Shared(std::rc::Rc::new(7)));
+		assert_eq!(local::Shared::try_from(shared).map(|shared| *shared.0), Ok(7));
+	}
+}
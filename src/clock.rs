@@ -0,0 +1,105 @@
+//! Injectable clocks used for timestamping events.
+
+use std::{
+	sync::{
+		atomic::{AtomicI64, Ordering},
+		Arc,
+	},
+	time::Duration,
+};
+
+/// A source of wall-clock time.
+pub trait Clock {
+	/// Returns the current time as milliseconds since the Unix epoch.
+	fn now_millis(&self) -> i64;
+}
+
+impl<C> Clock for &C
+where
+	C: Clock + ?Sized,
+{
+	fn now_millis(&self) -> i64 {
+		(**self).now_millis()
+	}
+}
+
+impl<C> Clock for Arc<C>
+where
+	C: Clock + ?Sized,
+{
+	fn now_millis(&self) -> i64 {
+		(**self).now_millis()
+	}
+}
+
+/// The system wall clock.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+	fn now_millis(&self) -> i64 {
+		chrono::Utc::now().timestamp_millis()
+	}
+}
+
+/// A clock that is stopped at a fixed time.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct FixedClock(pub i64);
+
+impl Clock for FixedClock {
+	fn now_millis(&self) -> i64 {
+		self.0
+	}
+}
+
+/// A clock that only moves when advanced manually.
+///
+/// Useful for simulations and replays. Share it via an [`Arc`] to advance it from elsewhere.
+#[derive(Debug, Default)]
+pub struct ManualClock {
+	now: AtomicI64,
+}
+
+impl ManualClock {
+	/// Creates a new clock starting at the given time (in ms).
+	pub fn new(now: i64) -> Self {
+		Self { now: AtomicI64::new(now) }
+	}
+
+	/// Sets the current time (in ms).
+	pub fn set(&self, now: i64) {
+		self.now.store(now, Ordering::SeqCst);
+	}
+
+	/// Advances the clock by the given duration.
+	pub fn advance(&self, by: Duration) {
+		self.now.fetch_add(by.as_millis() as i64, Ordering::SeqCst);
+	}
+}
+
+impl Clock for ManualClock {
+	fn now_millis(&self) -> i64 {
+		self.now.load(Ordering::SeqCst)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::*;
+
+	#[test]
+	fn test_clocks() {
+		let clock = Arc::new(ManualClock::new(1_000));
+		let event = TimestampedEvent::<u32>::with_clock(1u32, &clock);
+		assert_eq!(event.timestamp, 1_000);
+		clock.advance(Duration::from_millis(250));
+		assert_eq!(TimestampedEvent::<u32>::with_clock(1u32, clock.clone()).timestamp, 1_250);
+		clock.set(0);
+		assert_eq!(clock.now_millis(), 0);
+
+		assert_eq!(TimestampedEvent::<u32>::with_clock(1u32, FixedClock(42)).timestamp, 42);
+		assert_eq!(Res::<u32>::new_at(Targets::All, 1u32, 7).event.timestamp, 7);
+		assert!(SystemClock.now_millis() > 0);
+	}
+}
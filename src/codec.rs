@@ -0,0 +1,169 @@
+//! Pluggable codecs for encoding and decoding wire envelopes.
+//!
+//! A [`Codec`] turns any serializable envelope ([`Req`], [`Res`], [`Error`]) into bytes and back.
+//! Concrete codecs are gated behind cargo features:
+//! - `json` - [`Json`]
+//! - `msgpack` - [`MsgPack`]
+//! - `cbor` - [`Cbor`]
+//!
+//! The binary codecs encode targets with the [`compact`](crate::compact) encoding.
+//!
+//! [`Req`]: crate::Req
+//! [`Res`]: crate::Res
+//! [`Error`]: crate::Error
+
+use crate::NetworkError;
+
+/// A codec error.
+///
+/// Any codec error maps onto [`NetworkError::InvalidMessage`] when surfaced to the peer.
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq, Hash)]
+pub enum CodecError {
+	/// The message could not be encoded.
+	#[error("failed to encode message: {0}")]
+	Encode(String),
+	/// The message could not be decoded.
+	#[error("failed to decode message: {0}")]
+	Decode(String),
+}
+
+impl From<CodecError> for NetworkError {
+	fn from(_: CodecError) -> Self {
+		NetworkError::InvalidMessage
+	}
+}
+
+/// A wire format used to encode and decode envelopes.
+pub trait Codec {
+	/// Encodes a value into bytes.
+	fn encode<T>(&self, value: &T) -> Result<Vec<u8>, CodecError>
+	where
+		T: serde::Serialize;
+
+	/// Decodes a value from bytes.
+	fn decode<T>(&self, bytes: &[u8]) -> Result<T, CodecError>
+	where
+		T: serde::de::DeserializeOwned;
+}
+
+/// A JSON codec.
+#[cfg(feature = "json")]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Json;
+
+#[cfg(feature = "json")]
+impl Codec for Json {
+	fn encode<T>(&self, value: &T) -> Result<Vec<u8>, CodecError>
+	where
+		T: serde::Serialize,
+	{
+		serde_json::to_vec(value).map_err(|err| CodecError::Encode(err.to_string()))
+	}
+
+	fn decode<T>(&self, bytes: &[u8]) -> Result<T, CodecError>
+	where
+		T: serde::de::DeserializeOwned,
+	{
+		serde_json::from_slice(bytes).map_err(|err| CodecError::Decode(err.to_string()))
+	}
+}
+
+/// A MessagePack codec.
+///
+/// Structs are encoded as maps to stay compatible across field reordering, targets with the [`compact`](crate::compact) encoding.
+#[cfg(feature = "msgpack")]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct MsgPack;
+
+#[cfg(feature = "msgpack")]
+impl Codec for MsgPack {
+	fn encode<T>(&self, value: &T) -> Result<Vec<u8>, CodecError>
+	where
+		T: serde::Serialize,
+	{
+		crate::compact::scope(|| rmp_serde::to_vec_named(value)).map_err(|err| CodecError::Encode(err.to_string()))
+	}
+
+	fn decode<T>(&self, bytes: &[u8]) -> Result<T, CodecError>
+	where
+		T: serde::de::DeserializeOwned,
+	{
+		crate::compact::scope(|| rmp_serde::from_slice(bytes)).map_err(|err| CodecError::Decode(err.to_string()))
+	}
+}
+
+/// A CBOR codec.
+///
+/// Targets are encoded with the [`compact`](crate::compact) encoding.
+#[cfg(feature = "cbor")]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Cbor;
+
+#[cfg(feature = "cbor")]
+impl Codec for Cbor {
+	fn encode<T>(&self, value: &T) -> Result<Vec<u8>, CodecError>
+	where
+		T: serde::Serialize,
+	{
+		let mut buf = Vec::new();
+		crate::compact::scope(|| ciborium::into_writer(value, &mut buf)).map_err(|err| CodecError::Encode(err.to_string()))?;
+		Ok(buf)
+	}
+
+	fn decode<T>(&self, bytes: &[u8]) -> Result<T, CodecError>
+	where
+		T: serde::de::DeserializeOwned,
+	{
+		crate::compact::scope(|| ciborium::from_reader(bytes)).map_err(|err| CodecError::Decode(err.to_string()))
+	}
+}
+
+#[cfg(all(test, any(feature = "json", feature = "msgpack", feature = "cbor")))]
+mod tests {
+	use super::*;
+	use crate::*;
+
+	#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+	enum Action {
+		Move { x: i32, y: i32 },
+		Leave,
+	}
+
+	fn roundtrip(codec: impl Codec) {
+		let req = Req::<Action>::new(Target::new_random(), Action::Move { x: 1, y: -1 }, CorrelationId::new_v4());
+		let bytes = codec.encode(&req).unwrap();
+		assert_eq!(codec.decode::<Req<Action>>(&bytes).unwrap(), req);
+
+		let res = Res::<Action>::new(Targets::All, Action::Leave);
+		let bytes = codec.encode(&res).unwrap();
+		let decoded = codec.decode::<Res<Action>>(&bytes).unwrap();
+		assert_eq!(decoded, res);
+		assert_eq!(decoded.event.timestamp, res.event.timestamp);
+
+		let err = Error::<NetworkError>::new(Target::new_anon(3), NetworkError::RateLimited, CorrelationId::new_v4());
+		let bytes = codec.encode(&err).unwrap();
+		assert_eq!(codec.decode::<Error<NetworkError>>(&bytes).unwrap(), err);
+
+		let decoded = codec.decode::<Req<Action>>(&[0xff, 0x00, 0x13]);
+		assert!(matches!(decoded, Err(CodecError::Decode(_))));
+		assert_eq!(NetworkError::from(decoded.unwrap_err()), NetworkError::InvalidMessage);
+	}
+
+	#[test]
+	#[cfg(feature = "json")]
+	fn test_json_roundtrip() {
+		roundtrip(Json);
+	}
+
+	#[test]
+	#[cfg(feature = "msgpack")]
+	fn test_msgpack_roundtrip() {
+		roundtrip(MsgPack);
+	}
+
+	#[test]
+	#[cfg(feature = "cbor")]
+	fn test_cbor_roundtrip() {
+		roundtrip(Cbor);
+	}
+}
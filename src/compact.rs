@@ -0,0 +1,346 @@
+//! A compact binary encoding for [`Target`] and [`Targets`].
+//!
+//! Every target is encoded as a tag byte followed by its payload:
+//! - `anon/{sid}` - `0x00` + varint session ID
+//! - `auth/{uuid}` - `0x01` + 16-byte UUID
+//! - `auth/{uuid}/{sid}` - `0x02` + 16-byte UUID + varint session ID
+//! - `bot/{uuid}` - `0x03` + 16-byte UUID
+//!
+//! Targets are encoded as a tag byte followed by their contents, lists being prefixed by a varint length.
+//!
+//! The envelopes ([`Req`], [`Res`], [`Error`]) use the encoding through [`serialize`] and [`deserialize`]
+//! within a [`scope`], which the binary codecs (`MsgPack` and `Cbor`) enter. Anywhere else (e.g. plain bincode
+//! or postcard) they keep the regular serde encoding, see the [crate docs](crate#wire-format).
+
+use std::cell::Cell;
+
+use crate::*;
+
+thread_local! {
+	/// Whether the compact encoding is enabled on this thread.
+	static ENABLED: Cell<bool> = const { Cell::new(false) };
+}
+
+/// The maximum nesting depth of decoded set expressions.
+const MAX_DEPTH: usize = 32;
+
+const TAG_ANON: u8 = 0x00;
+const TAG_AUTH_ALL: u8 = 0x01;
+const TAG_AUTH_SPECIFIC: u8 = 0x02;
+const TAG_BOT: u8 = 0x03;
+
+const TAG_ALL: u8 = 0x00;
+const TAG_FEW: u8 = 0x01;
+const TAG_GROUP: u8 = 0x02;
+const TAG_EXCEPT: u8 = 0x03;
+const TAG_UNION: u8 = 0x04;
+const TAG_INTERSECTION: u8 = 0x05;
+
+/// A value with a compact binary encoding.
+pub trait Compact: Sized {
+	/// Appends the encoded value to the buffer.
+	fn encode_compact(&self, buf: &mut Vec<u8>);
+
+	/// Decodes a value from the start of the bytes, advancing them past it.
+	fn decode_compact(bytes: &mut &[u8]) -> Result<Self, CodecError>;
+
+	/// Encodes the value into a new buffer.
+	fn to_compact(&self) -> Vec<u8> {
+		let mut buf = Vec::new();
+		self.encode_compact(&mut buf);
+		buf
+	}
+
+	/// Decodes a value spanning all of the bytes.
+	fn from_compact(mut bytes: &[u8]) -> Result<Self, CodecError> {
+		let value = Self::decode_compact(&mut bytes)?;
+		if !bytes.is_empty() {
+			return Err(CodecError::Decode(format!("{} trailing bytes", bytes.len())))
+		}
+		Ok(value)
+	}
+}
+
+impl Compact for Target {
+	fn encode_compact(&self, buf: &mut Vec<u8>) {
+		match self {
+			Self::Anon(session_id) => {
+				buf.push(TAG_ANON);
+				encode_varint(*session_id as u64, buf);
+			},
+			Self::Auth(AuthTarget::All(user_id)) => {
+				buf.push(TAG_AUTH_ALL);
+				buf.extend_from_slice(user_id.as_bytes());
+			},
+			Self::Auth(AuthTarget::Specific(user_id, session_id)) => {
+				buf.push(TAG_AUTH_SPECIFIC);
+				buf.extend_from_slice(user_id.as_bytes());
+				encode_varint(*session_id as u64, buf);
+			},
+			Self::Bot(bot_id) => {
+				buf.push(TAG_BOT);
+				buf.extend_from_slice(bot_id.as_bytes());
+			},
+		}
+	}
+
+	fn decode_compact(bytes: &mut &[u8]) -> Result<Self, CodecError> {
+		match decode_byte(bytes)? {
+			TAG_ANON => Ok(Self::new_anon(decode_session_id(bytes)?)),
+			TAG_AUTH_ALL => Ok(Self::new_auth(decode_uuid(bytes)?)),
+			TAG_AUTH_SPECIFIC => Ok(Self::new_auth_specific(decode_uuid(bytes)?, decode_session_id(bytes)?)),
+			TAG_BOT => Ok(Self::new_bot(decode_uuid(bytes)?)),
+			tag => Err(CodecError::Decode(format!("unknown target tag {tag:#04x}"))),
+		}
+	}
+}
+
+impl Compact for Targets {
+	fn encode_compact(&self, buf: &mut Vec<u8>) {
+		match self {
+			Self::All => buf.push(TAG_ALL),
+			Self::Few(targets) => {
+				buf.push(TAG_FEW);
+				encode_varint(targets.len() as u64, buf);
+				for target in targets {
+					target.encode_compact(buf);
+				}
+			},
+			Self::Group(group_id) => {
+				buf.push(TAG_GROUP);
+				buf.extend_from_slice(group_id.as_bytes());
+			},
+			Self::Except(inner, excluded) => {
+				buf.push(TAG_EXCEPT);
+				inner.encode_compact(buf);
+				excluded.encode_compact(buf);
+			},
+			Self::Union(targets) | Self::Intersection(targets) => {
+				buf.push(if matches!(self, Self::Union(..)) { TAG_UNION } else { TAG_INTERSECTION });
+				encode_varint(targets.len() as u64, buf);
+				for targets in targets {
+					targets.encode_compact(buf);
+				}
+			},
+		}
+	}
+
+	fn decode_compact(bytes: &mut &[u8]) -> Result<Self, CodecError> {
+		decode_targets(bytes, 0)
+	}
+}
+
+/// Decodes targets nested at the given depth.
+fn decode_targets(bytes: &mut &[u8], depth: usize) -> Result<Targets, CodecError> {
+	if depth > MAX_DEPTH {
+		return Err(CodecError::Decode("targets nested too deeply".to_string()))
+	}
+
+	match decode_byte(bytes)? {
+		TAG_ALL => Ok(Targets::All),
+		TAG_FEW => {
+			let len = decode_len(bytes)?;
+			(0..len).map(|_| Target::decode_compact(bytes)).collect::<Result<_, _>>().map(Targets::Few)
+		},
+		TAG_GROUP => Ok(Targets::Group(decode_uuid(bytes)?)),
+		TAG_EXCEPT => {
+			let inner = decode_targets(bytes, depth + 1)?;
+			let excluded = decode_targets(bytes, depth + 1)?;
+			Ok(Targets::Except(Box::new(inner), Box::new(excluded)))
+		},
+		tag @ (TAG_UNION | TAG_INTERSECTION) => {
+			let len = decode_len(bytes)?;
+			let targets = (0..len).map(|_| decode_targets(bytes, depth + 1)).collect::<Result<_, _>>()?;
+			Ok(if tag == TAG_UNION { Targets::Union(targets) } else { Targets::Intersection(targets) })
+		},
+		tag => Err(CodecError::Decode(format!("unknown targets tag {tag:#04x}"))),
+	}
+}
+
+/// Appends an unsigned LEB128 varint.
+fn encode_varint(mut value: u64, buf: &mut Vec<u8>) {
+	while value >= 0x80 {
+		buf.push(value as u8 | 0x80);
+		value >>= 7;
+	}
+	buf.push(value as u8);
+}
+
+/// Decodes an unsigned LEB128 varint.
+fn decode_varint(bytes: &mut &[u8]) -> Result<u64, CodecError> {
+	let mut value = 0u64;
+	for shift in (0..64).step_by(7) {
+		let byte = decode_byte(bytes)?;
+		value |= ((byte & 0x7f) as u64) << shift;
+		if byte & 0x80 == 0 {
+			return Ok(value)
+		}
+	}
+	Err(CodecError::Decode("varint too long".to_string()))
+}
+
+/// Decodes a session ID.
+fn decode_session_id(bytes: &mut &[u8]) -> Result<SessionId, CodecError> {
+	let value = decode_varint(bytes)?;
+	SessionId::try_from(value).map_err(|_| CodecError::Decode(format!("session ID {value} out of range")))
+}
+
+/// Decodes a list length, bounded by the remaining bytes so that a bogus length cannot cause a huge allocation.
+fn decode_len(bytes: &mut &[u8]) -> Result<usize, CodecError> {
+	let len = decode_varint(bytes)?;
+	usize::try_from(len)
+		.ok()
+		.filter(|len| *len <= bytes.len())
+		.ok_or_else(|| CodecError::Decode(format!("length {len} exceeds the remaining bytes")))
+}
+
+/// Decodes a 16-byte UUID.
+fn decode_uuid(bytes: &mut &[u8]) -> Result<Uuid, CodecError> {
+	let Some((uuid, rest)) = bytes.split_first_chunk::<16>() else {
+		return Err(CodecError::Decode("unexpected end of input".to_string()))
+	};
+	*bytes = rest;
+	Ok(Uuid::from_bytes(*uuid))
+}
+
+/// Decodes a single byte.
+fn decode_byte(bytes: &mut &[u8]) -> Result<u8, CodecError> {
+	let Some((byte, rest)) = bytes.split_first() else {
+		return Err(CodecError::Decode("unexpected end of input".to_string()))
+	};
+	*bytes = rest;
+	Ok(*byte)
+}
+
+/// Runs `f` with the compact encoding enabled on this thread.
+///
+/// Wrap the (de)serialization of envelopes with a custom binary serializer in it to opt into the compact encoding.
+pub fn scope<R>(f: impl FnOnce() -> R) -> R {
+	/// Restores the previous state, even if `f` panics.
+	struct Restore(bool);
+
+	impl Drop for Restore {
+		fn drop(&mut self) {
+			ENABLED.set(self.0);
+		}
+	}
+
+	let _restore = Restore(ENABLED.replace(true));
+	f()
+}
+
+/// Checks whether the compact encoding is enabled on this thread, see [`scope`].
+pub fn is_enabled() -> bool {
+	ENABLED.get()
+}
+
+/// Serializes the value as compact bytes for binary formats within a [`scope`] and regularly otherwise.
+///
+/// Use as `#[serde(with = "wire::compact")]`.
+pub fn serialize<T, S>(value: &T, serializer: S) -> Result<S::Ok, S::Error>
+where
+	T: Compact + serde::Serialize,
+	S: serde::Serializer,
+{
+	if serializer.is_human_readable() || !is_enabled() {
+		value.serialize(serializer)
+	} else {
+		serializer.serialize_bytes(&value.to_compact())
+	}
+}
+
+/// Deserializes the value from compact bytes for binary formats within a [`scope`] and regularly otherwise.
+///
+/// Use as `#[serde(with = "wire::compact")]`.
+pub fn deserialize<'de, T, D>(deserializer: D) -> Result<T, D::Error>
+where
+	T: Compact + serde::Deserialize<'de>,
+	D: serde::Deserializer<'de>,
+{
+	if deserializer.is_human_readable() || !is_enabled() {
+		T::deserialize(deserializer)
+	} else {
+		deserializer.deserialize_bytes(CompactVisitor(std::marker::PhantomData))
+	}
+}
+
+/// A visitor of compact bytes.
+struct CompactVisitor<T>(std::marker::PhantomData<T>);
+
+impl<'de, T> serde::de::Visitor<'de> for CompactVisitor<T>
+where
+	T: Compact,
+{
+	type Value = T;
+
+	fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		f.write_str("compact bytes")
+	}
+
+	fn visit_bytes<E>(self, bytes: &[u8]) -> Result<Self::Value, E>
+	where
+		E: serde::de::Error,
+	{
+		T::from_compact(bytes).map_err(E::custom)
+	}
+
+	fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+	where
+		A: serde::de::SeqAccess<'de>,
+	{
+		let mut bytes = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+		while let Some(byte) = seq.next_element()? {
+			bytes.push(byte);
+		}
+		self.visit_bytes(&bytes)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_compact_roundtrip() {
+		let mut pool = UserPool::default();
+		let (alice, bob, bot) = (pool.next_auth(), pool.next_anon(), pool.next_bot());
+		let targets = [
+			Targets::All,
+			Targets::none(),
+			Targets::Few(vec![alice, alice.for_all(), bob, bot, Target::new_anon(SessionId::MAX)]),
+			Targets::Group(GroupId::new_v4()) - alice,
+			Targets::Union(vec![Targets::All, bot.into()]),
+			Targets::Intersection(vec![Targets::Group(GroupId::new_v4()), alice.into()]),
+		];
+		for targets in targets {
+			assert_eq!(Targets::from_compact(&targets.to_compact()), Ok(targets));
+		}
+
+		assert_eq!(bob.to_compact(), vec![TAG_ANON, 2]);
+		assert_eq!(alice.to_compact().len(), 1 + 16 + 1);
+		assert_eq!(Target::new_auth_specific(Uuid::nil(), 300).to_compact().len(), 1 + 16 + 2);
+		assert_eq!(Target::from_compact(&bot.to_compact()), Ok(bot));
+
+		assert!(Target::from_compact(&[TAG_BOT, 0]).is_err());
+		assert!(Target::from_compact(&[0x07]).is_err());
+		assert!(Target::from_compact(&[TAG_ANON, 0xff, 0xff, 0xff, 0xff, 0x7f]).is_err());
+		assert!(Targets::from_compact(&[TAG_FEW, 0xff, 0xff, 0x03]).is_err());
+		assert!(Targets::from_compact(&[TAG_ALL, TAG_ALL]).is_err());
+		assert!(Targets::from_compact(&[TAG_EXCEPT; MAX_DEPTH + 2]).is_err());
+	}
+
+	#[test]
+	#[cfg(feature = "msgpack")]
+	fn test_compact_scope() {
+		use crate::codec::MsgPack;
+
+		let res = Res::<u32>::new(Targets::Few(vec![UserPool::default().next_auth()]), 1u32);
+		let plain = rmp_serde::to_vec_named(&res).unwrap();
+		let compact = MsgPack.encode(&res).unwrap();
+		assert!(compact.len() < plain.len());
+		assert_eq!(rmp_serde::from_slice::<Res<u32>>(&plain).unwrap(), res);
+		assert_eq!(MsgPack.decode::<Res<u32>>(&compact).unwrap(), res);
+		assert_eq!(scope(|| rmp_serde::to_vec_named(&res).unwrap()), compact);
+		assert!(!is_enabled());
+	}
+}
@@ -0,0 +1,211 @@
+//! Common utilities for tracking pending requests.
+
+use std::{
+	collections::HashMap,
+	time::{Duration, Instant},
+};
+
+use crate::*;
+
+/// A request awaiting a reply.
+struct Pending<C> {
+	/// The target that sent the request.
+	from: Target,
+	/// The instant after which the request times out.
+	deadline: Option<Instant>,
+	/// The continuation to resume once the request is resolved.
+	continuation: C,
+}
+
+/// A tracker of pending requests keyed by their [`CorrelationId`].
+///
+/// Every outgoing [`Req`] is registered along with a continuation `C` (e.g. a oneshot sender or a callback),
+/// which is handed back once a reply ([`Res`] or [`Error`]) with a matching correlation ID arrives, the request times out,
+/// gets cancelled or its session disconnects.
+pub struct Correlator<C> {
+	pending: HashMap<CorrelationId, Pending<C>>,
+	timeout: Option<Duration>,
+}
+
+impl<C> Correlator<C> {
+	/// Creates a new correlator without a default timeout.
+	pub fn new() -> Self {
+		Self {
+			pending: HashMap::new(),
+			timeout: None,
+		}
+	}
+
+	/// Creates a new correlator with a default per-request timeout.
+	pub fn with_timeout(timeout: Duration) -> Self {
+		Self {
+			pending: HashMap::new(),
+			timeout: Some(timeout),
+		}
+	}
+
+	/// Registers an outgoing request using the default timeout.
+	///
+	/// Returns the continuation of a previously registered request with the same correlation ID.
+	pub fn register<A>(&mut self, req: &Req<A>, continuation: C) -> Option<C> {
+		self.register_with_timeout(req, continuation, self.timeout)
+	}
+
+	/// Registers an outgoing request with a specific timeout (or none).
+	///
+	/// Returns the continuation of a previously registered request with the same correlation ID.
+	pub fn register_with_timeout<A>(&mut self, req: &Req<A>, continuation: C, timeout: Option<Duration>) -> Option<C> {
+		let pending = Pending {
+			from: req.from,
+			deadline: timeout.map(|timeout| Instant::now() + timeout),
+			continuation,
+		};
+
+		self.pending.insert(req.corrid, pending).map(|pending| pending.continuation)
+	}
+
+	/// Resolves a pending request by its correlation ID.
+	pub fn resolve(&mut self, corrid: &CorrelationId) -> Option<C> {
+		self.pending.remove(corrid).map(|pending| pending.continuation)
+	}
+
+	/// Resolves the pending request the response is a direct reply to.
+	///
+	/// Responses without a correlation ID (broadcasts) never resolve anything.
+	pub fn resolve_res<E>(&mut self, res: &Res<E>) -> Option<C> {
+		res.corrid.as_ref().and_then(|corrid| self.resolve(corrid))
+	}
+
+	/// Resolves the pending request the error is a reply to.
+	pub fn resolve_error<E>(&mut self, error: &Error<E>) -> Option<C> {
+		self.resolve(&error.corrid)
+	}
+
+	/// Cancels a pending request, so that a late reply to it is ignored.
+	pub fn cancel(&mut self, corrid: &CorrelationId) -> Option<C> {
+		self.resolve(corrid)
+	}
+
+	/// Removes all requests that have timed out by now.
+	///
+	/// See [`Correlator::expire_at`].
+	pub fn expire(&mut self) -> Vec<(C, Error<NetworkError>)> {
+		self.expire_at(Instant::now())
+	}
+
+	/// Removes all requests that have timed out by the given instant.
+	///
+	/// Each expired continuation is returned along with a [`NetworkError::TimedOut`] error
+	/// directed to the target that sent the request.
+	pub fn expire_at(&mut self, now: Instant) -> Vec<(C, Error<NetworkError>)> {
+		let expired = self
+			.pending
+			.iter()
+			.filter(|(_, pending)| pending.deadline.is_some_and(|deadline| deadline <= now))
+			.map(|(corrid, _)| *corrid)
+			.collect::<Vec<_>>();
+
+		expired
+			.into_iter()
+			.filter_map(|corrid| self.pending.remove(&corrid).map(|pending| (corrid, pending)))
+			.map(|(corrid, pending)| (pending.continuation, Error::new(pending.from, NetworkError::TimedOut, corrid)))
+			.collect()
+	}
+
+	/// Removes all requests sent by the session that disconnected.
+	pub fn disconnect<M>(&mut self, event: &Disconnected<M>) -> Vec<(CorrelationId, C)> {
+		let target = Target::new_deduced(event.user_id, event.session_id);
+		let disconnected = self
+			.pending
+			.iter()
+			.filter(|(_, pending)| pending.from == target)
+			.map(|(corrid, _)| *corrid)
+			.collect::<Vec<_>>();
+
+		disconnected
+			.into_iter()
+			.filter_map(|corrid| self.pending.remove(&corrid).map(|pending| (corrid, pending.continuation)))
+			.collect()
+	}
+
+	/// Removes all pending requests.
+	pub fn drain(&mut self) -> impl Iterator<Item = (CorrelationId, C)> + '_ {
+		self.pending.drain().map(|(corrid, pending)| (corrid, pending.continuation))
+	}
+
+	/// Returns the earliest deadline of all pending requests.
+	///
+	/// Useful for scheduling the next call to [`Correlator::expire`].
+	pub fn next_deadline(&self) -> Option<Instant> {
+		self.pending.values().filter_map(|pending| pending.deadline).min()
+	}
+
+	/// Checks whether a request is pending.
+	pub fn is_pending(&self, corrid: &CorrelationId) -> bool {
+		self.pending.contains_key(corrid)
+	}
+
+	/// Returns the number of pending requests.
+	pub fn len(&self) -> usize {
+		self.pending.len()
+	}
+
+	/// Checks whether there are no pending requests.
+	pub fn is_empty(&self) -> bool {
+		self.pending.is_empty()
+	}
+}
+
+impl<C> Default for Correlator<C> {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl<C> std::fmt::Debug for Correlator<C> {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.debug_struct("Correlator")
+			.field("pending", &self.pending.keys().collect::<Vec<_>>())
+			.field("timeout", &self.timeout)
+			.finish()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_correlator() {
+		let mut pool = UserPool::default();
+		let (alice, bob) = (pool.next_auth(), pool.next_anon());
+		let mut correlator = Correlator::with_timeout(Duration::from_secs(1));
+
+		let first = Req::<u32>::new(alice, 1u32, CorrelationId::new_v4());
+		let second = Req::<u32>::new(alice, 2u32, CorrelationId::new_v4());
+		let third = Req::<u32>::new(bob, 3u32, CorrelationId::new_v4());
+		let fourth = Req::<u32>::new(bob, 4u32, CorrelationId::new_v4());
+		let fifth = CorrelationId::new_v4();
+		correlator.register(&first, "first");
+		correlator.register_with_timeout(&second, "second", None);
+		correlator.register(&third, "third");
+		correlator.register(&fourth, "fourth");
+		correlator.register(&Req::<u32>::new(bob, 5u32, fifth), "fifth");
+		assert_eq!(correlator.len(), 5);
+
+		let error = Error::<NetworkError>::new(alice, NetworkError::RateLimited, first.corrid);
+		assert_eq!(correlator.resolve_error(&error), Some("first"));
+		assert_eq!(correlator.resolve_error(&error), None);
+		assert_eq!(correlator.cancel(&third.corrid), Some("third"));
+		assert_eq!(correlator.resolve_res(&Res::<u32>::new(bob, 0u32)), None);
+		assert_eq!(correlator.resolve_res(&Res::<u32>::reply_to(&fourth, 0u32)), Some("fourth"));
+
+		let expired = correlator.expire_at(Instant::now() + Duration::from_secs(2));
+		assert_eq!(expired, vec![("fifth", Error::new(bob, NetworkError::TimedOut, fifth))]);
+
+		let Target::Auth(AuthTarget::Specific(user_id, session_id)) = alice else { unreachable!() };
+		let disconnected = correlator.disconnect(&Disconnected::<()>::new(user_id, session_id));
+		assert_eq!(disconnected, vec![(second.corrid, "second")]);
+		assert!(correlator.is_empty());
+	}
+}
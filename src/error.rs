@@ -9,6 +9,7 @@ use crate::{CorrelationId, Target};
 #[error("error '{error}' directed to player '{to:?}'")]
 pub struct Error<E> {
 	/// The target.
+	#[serde(with = "crate::compact")]
 	pub to: Target,
 	/// The error.
 	pub error: E,
@@ -57,6 +58,51 @@ impl<E> Into<Vec<Error<E>>> for Error<E> {
 	}
 }
 
+/// An error returned when parsing an unknown [`WireObj`] kind.
+///
+/// [`WireObj`]: crate::WireObj
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq, Hash)]
+#[error("unknown kind '{0}'")]
+pub struct ParseKindError(pub String);
+
+/// An error returned when parsing a [`Target`] from its [`Display`](std::fmt::Display) format.
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ParseTargetError {
+	/// The target kind is not `anon`, `auth` or `bot`.
+	#[error("unknown target kind '{0}'")]
+	UnknownKind(String),
+	/// The user or bot ID is not a valid UUID.
+	#[error("invalid ID '{0}'")]
+	InvalidId(String),
+	/// The session ID is not a valid number.
+	#[error("invalid session ID '{0}'")]
+	InvalidSessionId(String),
+	/// The target has a wrong number of segments.
+	#[error("malformed target '{0}'")]
+	Malformed(String),
+}
+
+/// An error detected while tracking the sequence numbers of delivered events.
+///
+/// In any case the client has missed or misordered events and should request a resync.
+#[derive(thiserror::Error, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SequenceError {
+	/// Some events were skipped.
+	#[error("expected event {expected}, received event {received}")]
+	Gap {
+		/// The sequence number that was expected.
+		expected: u64,
+		/// The sequence number that was received.
+		received: u64,
+	},
+	/// An event was received again (or out of order).
+	#[error("event {0} was already received")]
+	Duplicate(u64),
+	/// An event with the last representable sequence number was received, after which no event can follow.
+	#[error("event {0} exhausted the sequence numbers")]
+	Overflow(u64),
+}
+
 /// A session error.
 ///
 /// # Usage
@@ -120,6 +166,12 @@ pub enum NetworkError {
 	/// Socket error.
 	#[error("Socket error.")]
 	SocketError(String),
+	/// No mutually supported protocol version.
+	#[error("No mutually supported protocol version.")]
+	VersionMismatch,
+	/// The request timed out.
+	#[error("The request timed out.")]
+	TimedOut,
 }
 
 #[cfg(feature = "i18n")]
@@ -130,6 +182,8 @@ impl i18n::LocalizedDisplay for NetworkError {
 			Self::RateLimited => "network-err-max_reached",
 			Self::InvalidMessage => "network-err-no_such_session",
 			Self::SocketError(msg) => return i18n::tr!(lang, "network-err-unauth", "what" = msg),
+			Self::VersionMismatch => "network-err-version_mismatch",
+			Self::TimedOut => "network-err-timed_out",
 		};
 
 		crate::i18n::LOCALES.query(lang, &i18n::Query::new(id).with_fallback(true)).unwrap()
@@ -188,6 +188,133 @@ impl<M> Clone for FirstConnected<M> {
 
 impl<M> Copy for FirstConnected<M> {}
 
+/// Event indicating a user disconnected from the server without having any other session active.
+///
+/// It is generic to allow for muxing into different handlers.
+pub struct LastDisconnected<M> {
+	/// The user id of the user.
+	pub user_id: UserId,
+	/// The session id of the user.
+	pub session_id: SessionId,
+	_phantom: std::marker::PhantomData<M>,
+}
+
+impl<M> LastDisconnected<M> {
+	/// Creates a new [`LastDisconnected`] event.
+	pub fn new(user_id: UserId, session_id: SessionId) -> Self {
+		Self {
+			user_id,
+			session_id,
+			_phantom: Default::default(),
+		}
+	}
+}
+
+impl<M> PartialEq for LastDisconnected<M> {
+	fn eq(&self, other: &Self) -> bool {
+		self.user_id == other.user_id && self.session_id == other.session_id
+	}
+}
+
+impl<M> Eq for LastDisconnected<M> {}
+
+impl<M> std::hash::Hash for LastDisconnected<M> {
+	fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+		self.user_id.hash(state);
+		self.session_id.hash(state);
+	}
+}
+
+impl<M> std::fmt::Debug for LastDisconnected<M> {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.debug_struct("LastDisconnected")
+			.field("user_id", &self.user_id)
+			.field("session_id", &self.session_id)
+			.finish()
+	}
+}
+
+impl<M> Clone for LastDisconnected<M> {
+	fn clone(&self) -> Self {
+		*self
+	}
+}
+
+impl<M> Copy for LastDisconnected<M> {}
+
+/// Event indicating a user resumed a suspended session after reconnecting.
+///
+/// Fired instead of [`Connected`], the session keeps its session ID.
+/// It is generic to allow for muxing into different handlers.
+pub struct Resumed<M> {
+	/// The user id of the user.
+	pub user_id: UserId,
+	/// The session id of the user.
+	pub session_id: SessionId,
+	_phantom: std::marker::PhantomData<M>,
+}
+
+impl<M> Resumed<M> {
+	/// Creates a new [`Resumed`] event.
+	pub fn new(user_id: UserId, session_id: SessionId) -> Self {
+		Self {
+			user_id,
+			session_id,
+			_phantom: Default::default(),
+		}
+	}
+}
+
+impl<M> PartialEq for Resumed<M> {
+	fn eq(&self, other: &Self) -> bool {
+		self.user_id == other.user_id && self.session_id == other.session_id
+	}
+}
+
+impl<M> Eq for Resumed<M> {}
+
+impl<M> std::hash::Hash for Resumed<M> {
+	fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+		self.user_id.hash(state);
+		self.session_id.hash(state);
+	}
+}
+
+impl<M> std::fmt::Debug for Resumed<M> {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.debug_struct("Resumed")
+			.field("user_id", &self.user_id)
+			.field("session_id", &self.session_id)
+			.finish()
+	}
+}
+
+impl<M> Clone for Resumed<M> {
+	fn clone(&self) -> Self {
+		*self
+	}
+}
+
+impl<M> Copy for Resumed<M> {}
+
+/// Event indicating a target joined a group.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+pub struct GroupJoined {
+	/// The group joined.
+	pub group_id: GroupId,
+	/// The target that joined the group.
+	pub target: Target,
+}
+
+/// Event indicating a target left a group.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+pub struct GroupLeft {
+	/// The group left.
+	pub group_id: GroupId,
+	/// The target that left the group.
+	pub target: Target,
+}
+
 impl<M> From<Connected<M>> for Target {
 	fn from(value: Connected<M>) -> Self {
 		Self::new_deduced(value.user_id, value.session_id)
@@ -206,6 +333,18 @@ impl<M> From<FirstConnected<M>> for Target {
 	}
 }
 
+impl<M> From<LastDisconnected<M>> for Target {
+	fn from(value: LastDisconnected<M>) -> Self {
+		Self::new_deduced(value.user_id, value.session_id)
+	}
+}
+
+impl<M> From<Resumed<M>> for Target {
+	fn from(value: Resumed<M>) -> Self {
+		Self::new_deduced(value.user_id, value.session_id)
+	}
+}
+
 impl<M> Into<(UserId, SessionId)> for Connected<M> {
 	fn into(self) -> (UserId, SessionId) {
 		(self.user_id, self.session_id)
@@ -223,3 +362,15 @@ impl<M> Into<(UserId, SessionId)> for FirstConnected<M> {
 		(self.user_id, self.session_id)
 	}
 }
+
+impl<M> From<LastDisconnected<M>> for (UserId, SessionId) {
+	fn from(value: LastDisconnected<M>) -> Self {
+		(value.user_id, value.session_id)
+	}
+}
+
+impl<M> From<Resumed<M>> for (UserId, SessionId) {
+	fn from(value: Resumed<M>) -> Self {
+		(value.user_id, value.session_id)
+	}
+}
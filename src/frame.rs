@@ -0,0 +1,225 @@
+//! Common utilities for top-level wire messages.
+
+use crate::*;
+
+/// A top-level message sent over the wire.
+///
+/// Wraps every envelope and control message into a single type so that both sides
+/// can demultiplex one stream without guessing which payload they received.
+///
+/// # Representation
+/// Frames are adjacently tagged with a `type` discriminator and a `data` payload:
+///
+/// ```json
+/// { "type": "request", "data": { "from": ..., "action": ..., "corrid": ... } }
+/// { "type": "ping", "data": 42 }
+/// { "type": "sequenced", "data": { "seq": 7, "frame": { "type": "response", "data": ... } } }
+/// { "type": "close" }
+/// ```
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(tag = "type", content = "data", rename_all = "snake_case")]
+pub enum Frame<A, E, Err> {
+	/// A request sent by a client.
+	Request(Req<A>),
+	/// A response sent by the server, without its targets.
+	Response(Delivered<E>),
+	/// An error sent by the server.
+	Error(Error<Err>),
+	/// A handshake opening sent by the client.
+	Hello(Hello),
+	/// A handshake reply sent by the server.
+	Welcome(Welcome),
+	/// A keep-alive probe carrying a nonce.
+	Ping(u64),
+	/// A reply to a [`Frame::Ping`] echoing its nonce.
+	Pong(u64),
+	/// A clock-sync probe sent by the client.
+	TimePing(TimePing),
+	/// A reply to a [`Frame::TimePing`] sent by the server.
+	TimePong(TimePong),
+	/// A notice that the sender is closing the connection.
+	Close,
+	/// A frame along with the per-session sequence number of its recipient, see [`Sequenced`].
+	Sequenced {
+		/// The per-session sequence number.
+		seq: u64,
+		/// The sequenced frame.
+		frame: Box<Frame<A, E, Err>>,
+	},
+}
+
+impl<A, E, Err> Frame<A, E, Err> {
+	/// Checks whether the frame is a control frame (not carrying an envelope).
+	pub fn is_control(&self) -> bool {
+		match self {
+			Self::Sequenced { frame, .. } => frame.is_control(),
+			_ => matches!(self, Self::Hello(..) | Self::Welcome(..) | Self::Ping(..) | Self::Pong(..) | Self::TimePing(..) | Self::TimePong(..) | Self::Close),
+		}
+	}
+
+	/// Returns the correlation ID of the frame if it carries one.
+	pub fn corrid(&self) -> Option<CorrelationId> {
+		match self {
+			Self::Request(req) => Some(req.corrid),
+			Self::Response(delivered) => delivered.corrid,
+			Self::Error(err) => Some(err.corrid),
+			Self::Sequenced { frame, .. } => frame.corrid(),
+			_ => None,
+		}
+	}
+}
+
+impl<A, E, Err> From<Req<A>> for Frame<A, E, Err> {
+	fn from(value: Req<A>) -> Self {
+		Self::Request(value)
+	}
+}
+
+impl<A, E, Err> From<Delivered<E>> for Frame<A, E, Err> {
+	fn from(value: Delivered<E>) -> Self {
+		Self::Response(value)
+	}
+}
+
+impl<A, E, Err> From<Res<E>> for Frame<A, E, Err> {
+	fn from(value: Res<E>) -> Self {
+		Self::Response(value.into())
+	}
+}
+
+impl<A, E, Err> From<Error<Err>> for Frame<A, E, Err> {
+	fn from(value: Error<Err>) -> Self {
+		Self::Error(value)
+	}
+}
+
+impl<A, E, Err> From<Hello> for Frame<A, E, Err> {
+	fn from(value: Hello) -> Self {
+		Self::Hello(value)
+	}
+}
+
+impl<A, E, Err> From<Welcome> for Frame<A, E, Err> {
+	fn from(value: Welcome) -> Self {
+		Self::Welcome(value)
+	}
+}
+
+impl<A, E, Err> From<TimePing> for Frame<A, E, Err> {
+	fn from(value: TimePing) -> Self {
+		Self::TimePing(value)
+	}
+}
+
+impl<A, E, Err> From<TimePong> for Frame<A, E, Err> {
+	fn from(value: TimePong) -> Self {
+		Self::TimePong(value)
+	}
+}
+
+impl<A, E, Err> PartialEq for Frame<A, E, Err>
+where
+	A: PartialEq,
+	E: PartialEq,
+	Err: PartialEq,
+{
+	fn eq(&self, other: &Self) -> bool {
+		match (self, other) {
+			(Self::Request(a), Self::Request(b)) => a == b,
+			(Self::Response(a), Self::Response(b)) => a == b,
+			(Self::Error(a), Self::Error(b)) => a == b,
+			(Self::Hello(a), Self::Hello(b)) => a == b,
+			(Self::Welcome(a), Self::Welcome(b)) => a == b,
+			(Self::Ping(a), Self::Ping(b)) => a == b,
+			(Self::Pong(a), Self::Pong(b)) => a == b,
+			(Self::TimePing(a), Self::TimePing(b)) => a == b,
+			(Self::TimePong(a), Self::TimePong(b)) => a == b,
+			(Self::Close, Self::Close) => true,
+			(Self::Sequenced { seq: a_seq, frame: a }, Self::Sequenced { seq: b_seq, frame: b }) => a_seq == b_seq && a == b,
+			_ => false,
+		}
+	}
+}
+
+impl<A, E, Err> Eq for Frame<A, E, Err>
+where
+	A: Eq,
+	E: Eq,
+	Err: Eq,
+{
+}
+
+impl<A, E, Err> std::fmt::Debug for Frame<A, E, Err>
+where
+	A: std::fmt::Debug,
+	E: std::fmt::Debug,
+	Err: std::fmt::Debug,
+{
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Self::Request(req) => f.debug_tuple("Request").field(req).finish(),
+			Self::Response(delivered) => f.debug_tuple("Response").field(delivered).finish(),
+			Self::Error(err) => f.debug_tuple("Error").field(err).finish(),
+			Self::Hello(hello) => f.debug_tuple("Hello").field(hello).finish(),
+			Self::Welcome(welcome) => f.debug_tuple("Welcome").field(welcome).finish(),
+			Self::Ping(nonce) => f.debug_tuple("Ping").field(nonce).finish(),
+			Self::Pong(nonce) => f.debug_tuple("Pong").field(nonce).finish(),
+			Self::TimePing(ping) => f.debug_tuple("TimePing").field(ping).finish(),
+			Self::TimePong(pong) => f.debug_tuple("TimePong").field(pong).finish(),
+			Self::Close => f.write_str("Close"),
+			Self::Sequenced { seq, frame } => f.debug_struct("Sequenced").field("seq", seq).field("frame", frame).finish(),
+		}
+	}
+}
+
+impl<A, E, Err> Clone for Frame<A, E, Err>
+where
+	A: Clone,
+	E: Clone,
+	Err: Clone,
+{
+	fn clone(&self) -> Self {
+		match self {
+			Self::Request(req) => Self::Request(req.clone()),
+			Self::Response(delivered) => Self::Response(delivered.clone()),
+			Self::Error(err) => Self::Error(err.clone()),
+			Self::Hello(hello) => Self::Hello(hello.clone()),
+			Self::Welcome(welcome) => Self::Welcome(welcome.clone()),
+			Self::Ping(nonce) => Self::Ping(*nonce),
+			Self::Pong(nonce) => Self::Pong(*nonce),
+			Self::TimePing(ping) => Self::TimePing(*ping),
+			Self::TimePong(pong) => Self::TimePong(*pong),
+			Self::Close => Self::Close,
+			Self::Sequenced { seq, frame } => Self::Sequenced { seq: *seq, frame: frame.clone() },
+		}
+	}
+}
+
+#[cfg(all(test, feature = "json"))]
+mod tests {
+	use super::*;
+	use crate::codec::{Codec, Json};
+
+	type TestFrame = Frame<u32, String, NetworkError>;
+
+	#[test]
+	fn test_frame_representation() {
+		let ping = TestFrame::Ping(42);
+		assert_eq!(Json.encode(&ping).unwrap(), br#"{"type":"ping","data":42}"#);
+		assert_eq!(Json.encode(&TestFrame::Close).unwrap(), br#"{"type":"close"}"#);
+
+		let req = Req::new(Target::new_anon(1), 7u32, CorrelationId::nil());
+		let encoded = Json.encode(&TestFrame::from(req.clone())).unwrap();
+		assert!(encoded.starts_with(br#"{"type":"request","data":"#));
+		assert_eq!(Json.decode::<TestFrame>(&encoded).unwrap(), TestFrame::Request(req));
+
+		let res = Res::<String>::new(Target::new_random(), "moved".to_string()).with_corrid(CorrelationId::nil());
+		let encoded = Json.encode(&TestFrame::from(res.clone())).unwrap();
+		assert!(!encoded.windows(b"targets".len()).any(|window| window == b"targets"));
+		assert_eq!(Json.decode::<TestFrame>(&encoded).unwrap(), TestFrame::Response(res.into()));
+
+		let err = Error::new(Target::new_anon(1), NetworkError::InvalidMessage, CorrelationId::nil());
+		let encoded = Json.encode(&TestFrame::from(err.clone())).unwrap();
+		assert_eq!(Json.decode::<TestFrame>(&encoded).unwrap(), TestFrame::Error(err));
+	}
+}
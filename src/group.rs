@@ -0,0 +1,124 @@
+//! Common utilities for tracking group (room) membership.
+
+use std::collections::HashMap;
+
+use crate::*;
+
+/// A registry of group members.
+///
+/// Members are kept as [`Target`]s, so a group can contain single sessions as well as all sessions
+/// of a user ([`AuthTarget::All`]). Groups are created on the first join and removed once empty.
+/// [`Targets::Group`] is resolved against the registry by [`Resolver::with_groups`].
+#[derive(Debug, Clone, Default)]
+pub struct GroupRegistry {
+	/// The members of every non-empty group, in joining order.
+	groups: HashMap<GroupId, Vec<Target>>,
+}
+
+impl GroupRegistry {
+	/// Creates a new empty registry.
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Adds a target to a group.
+	///
+	/// Returns [`None`] if the target already is a member of the group.
+	pub fn join(&mut self, group_id: GroupId, target: Target) -> Option<GroupJoined> {
+		let members = self.groups.entry(group_id).or_default();
+		if members.contains(&target) {
+			return None
+		}
+		members.push(target);
+
+		Some(GroupJoined { group_id, target })
+	}
+
+	/// Removes a target from a group.
+	///
+	/// Returns [`None`] if the target is not a member of the group.
+	pub fn leave(&mut self, group_id: GroupId, target: Target) -> Option<GroupLeft> {
+		let members = self.groups.get_mut(&group_id)?;
+		let index = members.iter().position(|member| *member == target)?;
+		members.remove(index);
+		if members.is_empty() {
+			self.groups.remove(&group_id);
+		}
+
+		Some(GroupLeft { group_id, target })
+	}
+
+	/// Removes a target from all groups it is a member of.
+	pub fn leave_all(&mut self, target: Target) -> Vec<GroupLeft> {
+		let group_ids = self
+			.groups
+			.iter()
+			.filter(|(_, members)| members.contains(&target))
+			.map(|(group_id, _)| *group_id)
+			.collect::<Vec<_>>();
+
+		group_ids.into_iter().filter_map(|group_id| self.leave(group_id, target)).collect()
+	}
+
+	/// Removes the session that disconnected from all groups it is a member of.
+	///
+	/// Memberships of all sessions of the user ([`AuthTarget::All`]) are kept.
+	pub fn disconnect<M>(&mut self, event: &Disconnected<M>) -> Vec<GroupLeft> {
+		self.leave_all(Target::new_deduced(event.user_id, event.session_id))
+	}
+
+	/// Returns the members of a group, in joining order.
+	pub fn members(&self, group_id: GroupId) -> &[Target] {
+		self.groups.get(&group_id).map_or(&[], Vec::as_slice)
+	}
+
+	/// Checks whether the target is a member of a group.
+	pub fn contains(&self, group_id: GroupId, target: &Target) -> bool {
+		self.members(group_id).contains(target)
+	}
+
+	/// Returns all non-empty groups.
+	pub fn groups(&self) -> impl Iterator<Item = GroupId> + '_ {
+		self.groups.keys().copied()
+	}
+
+	/// Returns the number of non-empty groups.
+	pub fn len(&self) -> usize {
+		self.groups.len()
+	}
+
+	/// Checks whether there are no non-empty groups.
+	pub fn is_empty(&self) -> bool {
+		self.groups.is_empty()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_group_registry() {
+		let mut pool = UserPool::default();
+		let (alice, bob) = (pool.next_auth(), pool.next_anon());
+		let (lobby, game) = (GroupId::new_v4(), GroupId::new_v4());
+		let mut groups = GroupRegistry::new();
+
+		assert_eq!(groups.join(lobby, alice), Some(GroupJoined { group_id: lobby, target: alice }));
+		assert_eq!(groups.join(lobby, alice), None);
+		groups.join(lobby, bob);
+		groups.join(game, bob);
+		groups.join(game, alice.for_all());
+		assert_eq!(groups.members(lobby), &[alice, bob]);
+		assert_eq!(groups.len(), 2);
+
+		assert_eq!(groups.leave(lobby, alice), Some(GroupLeft { group_id: lobby, target: alice }));
+		assert_eq!(groups.leave(lobby, alice), None);
+		let Target::Anon(session_id) = bob else { unreachable!() };
+		let mut left = groups.disconnect(&Disconnected::<()>::new(ANON_USER_ID, session_id));
+		left.sort_by_key(|left| left.group_id == game);
+		assert_eq!(left, vec![GroupLeft { group_id: lobby, target: bob }, GroupLeft { group_id: game, target: bob }]);
+		assert_eq!(groups.groups().collect::<Vec<_>>(), vec![game]);
+		assert!(groups.contains(game, &alice.for_all()));
+	}
+}
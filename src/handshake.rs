@@ -0,0 +1,158 @@
+//! Common utilities for protocol version negotiation.
+//!
+//! A client opens a connection by sending a [`Hello`] with every protocol version it supports.
+//! The server picks the highest version compatible with one it supports and replies with a [`Welcome`],
+//! or rejects the connection with [`NetworkError::VersionMismatch`].
+
+use crate::*;
+
+/// A protocol version.
+///
+/// Versions with the same major version are compatible with each other.
+#[derive(Debug, serde::Serialize, serde::Deserialize, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ProtocolVersion {
+	/// The major version, bumped on breaking changes.
+	pub major: u16,
+	/// The minor version, bumped on backwards compatible changes.
+	pub minor: u16,
+}
+
+impl ProtocolVersion {
+	/// Creates a new protocol version.
+	pub const fn new(major: u16, minor: u16) -> Self {
+		Self { major, minor }
+	}
+
+	/// Checks whether the two versions are compatible with each other.
+	pub fn is_compatible(&self, other: &Self) -> bool {
+		self.major == other.major
+	}
+}
+
+impl std::fmt::Display for ProtocolVersion {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(f, "{}.{}", self.major, self.minor)
+	}
+}
+
+/// A handshake message sent by the client when opening a connection.
+#[derive(Debug, serde::Serialize, serde::Deserialize, Clone, PartialEq, Eq, Hash)]
+pub struct Hello {
+	/// The protocol versions supported by the client.
+	pub versions: Vec<ProtocolVersion>,
+	/// The capabilities supported by the client.
+	pub capabilities: Vec<String>,
+}
+
+impl Hello {
+	/// Creates a new client hello.
+	pub fn new(versions: impl Into<Vec<ProtocolVersion>>) -> Self {
+		Self {
+			versions: versions.into(),
+			capabilities: Vec::new(),
+		}
+	}
+
+	/// Adds a capability to the client hello.
+	pub fn with_capability(mut self, capability: impl Into<String>) -> Self {
+		self.capabilities.push(capability.into());
+		self
+	}
+
+	/// Negotiates a protocol version against the versions and capabilities supported by the server.
+	///
+	/// Every pair of compatible client and server versions can speak the lower of the two, so the highest
+	/// such version is picked, along with the capabilities supported by both sides.
+	pub fn negotiate(&self, versions: &[ProtocolVersion], capabilities: impl IntoIterator<Item = impl AsRef<str>>) -> Result<Welcome, NetworkError> {
+		let version = self
+			.versions
+			.iter()
+			.flat_map(|client| versions.iter().filter(|server| client.is_compatible(server)).map(move |server| *client.min(server)))
+			.max()
+			.ok_or(NetworkError::VersionMismatch)?;
+		let supported = capabilities.into_iter().collect::<Vec<_>>();
+		let capabilities = self
+			.capabilities
+			.iter()
+			.filter(|capability| supported.iter().any(|supported| supported.as_ref() == capability.as_str()))
+			.cloned()
+			.collect();
+
+		Ok(Welcome { version, capabilities })
+	}
+}
+
+/// A handshake message sent by the server in reply to a [`Hello`].
+#[derive(Debug, serde::Serialize, serde::Deserialize, Clone, PartialEq, Eq, Hash)]
+pub struct Welcome {
+	/// The negotiated protocol version.
+	pub version: ProtocolVersion,
+	/// The negotiated capabilities.
+	pub capabilities: Vec<String>,
+}
+
+impl Welcome {
+	/// Checks whether a capability was negotiated.
+	pub fn has_capability(&self, capability: &str) -> bool {
+		self.capabilities.iter().any(|c| c == capability)
+	}
+}
+
+/// A session's target along with its negotiated protocol version.
+#[derive(Debug, serde::Serialize, serde::Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct VersionedTarget {
+	/// The target.
+	pub target: Target,
+	/// The negotiated protocol version.
+	pub version: ProtocolVersion,
+}
+
+impl VersionedTarget {
+	/// Creates a new versioned target.
+	pub fn new(target: impl Into<Target>, version: ProtocolVersion) -> Self {
+		Self {
+			target: target.into(),
+			version,
+		}
+	}
+}
+
+impl Target {
+	/// Attaches a negotiated protocol version to the target.
+	pub fn with_version(self, version: ProtocolVersion) -> VersionedTarget {
+		VersionedTarget::new(self, version)
+	}
+}
+
+impl From<VersionedTarget> for Target {
+	fn from(value: VersionedTarget) -> Self {
+		value.target
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_negotiate() {
+		let server = [ProtocolVersion::new(1, 0), ProtocolVersion::new(1, 1), ProtocolVersion::new(2, 0)];
+		let hello = Hello::new(vec![ProtocolVersion::new(1, 0), ProtocolVersion::new(1, 1)])
+			.with_capability("compression")
+			.with_capability("resume");
+
+		let welcome = hello.negotiate(&server, ["resume"]).unwrap();
+		assert_eq!(welcome.version, ProtocolVersion::new(1, 1));
+		assert!(welcome.has_capability("resume"));
+		assert!(!welcome.has_capability("compression"));
+
+		// a newer minor version falls back to the highest compatible one
+		let hello = Hello::new(vec![ProtocolVersion::new(1, 4)]);
+		let welcome = hello.negotiate(&server[..2], vec!["resume".to_string()]).unwrap();
+		assert_eq!(welcome.version, ProtocolVersion::new(1, 1));
+		assert!(welcome.capabilities.is_empty());
+
+		let hello = Hello::new(vec![ProtocolVersion::new(3, 0)]);
+		assert_eq!(hello.negotiate(&server, [] as [&str; 0]), Err(NetworkError::VersionMismatch));
+	}
+}
@@ -1,8 +1,10 @@
 //! A facade and utility crate for an action-and-event
 //! sessions-based on-the-wire communication.
 //!
-//! Provides [`Req`], [`Res`] and [`Error`] as a generic API for req-res communication.
-//! Also provides a utility macro for implementing actions and events for use with communication and also [`bevy`].
+//! Provides [`Req`], [`Res`] and [`Error`] as a generic API for req-res communication,
+//! multiplexed over a single stream via [`Frame`].
+//! Also provides a utility macro for implementing actions and events for use with communication and also [`bevy`]
+//! (see the `bevy` module, behind the `bevy` feature).
 //!
 //! # Example
 //! ```
@@ -17,6 +19,12 @@
 //!
 //! [`bevy`]: https://bevyengine.org
 //!
+//! # Wire format
+//! The binary codecs (`MsgPack` and `Cbor`) encode the targets of the envelopes with the [`compact`] encoding,
+//! which peers using the regular serde encoding cannot decode (nor the other way around). Tie the switch to the
+//! major [`ProtocolVersion`] negotiated in the handshake, so that mismatched peers are rejected instead of failing
+//! to decode. Other serializers keep the regular encoding unless run within a [`compact::scope`].
+//!
 //! # Fluent i18n
 //! `wire` exports a couple of common error types which use [`project-fluent`] as a
 //! way of specifying locales, therefore it exports its translations via [`fluent-templates`].
@@ -24,29 +32,98 @@
 //! [`project-fluent`]: https://projectfluent.org
 //! [`fluent-templates`]: https://github.com/XAMPPRocky/fluent-templates
 
+pub mod clock;
+pub use clock::{Clock, FixedClock, ManualClock, SystemClock};
+
+pub mod codec;
+pub use codec::{Codec, CodecError};
+
+pub mod compact;
+pub use compact::Compact;
+
+pub mod correlator;
+pub use correlator::Correlator;
+
 pub mod error;
-pub use error::{Error, NetworkError, SessionError};
+pub use error::{Error, NetworkError, ParseKindError, ParseTargetError, SequenceError, SessionError};
 
 pub mod events;
-pub use events::{Connected, Disconnected, FirstConnected, Undetermined};
+pub use events::{Connected, Disconnected, FirstConnected, GroupJoined, GroupLeft, LastDisconnected, Resumed, Undetermined};
+
+pub mod frame;
+pub use frame::Frame;
+
+pub mod group;
+pub use group::GroupRegistry;
+
+pub mod handshake;
+pub use handshake::{Hello, ProtocolVersion, VersionedTarget, Welcome};
 
 pub mod req;
 pub use req::Req;
 
 pub mod res;
-pub use res::{Res, TimestampedEvent};
+pub use res::{Delivered, EncodedRes, Res, TimestampedEvent};
+
+pub mod resolver;
+pub use resolver::{Destination, ResolveScratch, Resolver, SessionTable};
+
+pub mod resume;
+pub use resume::{Replay, ResumeEvents, ResumeToken, Resumer};
+
+pub mod sequence;
+pub use sequence::{SeqTracker, Sequenced, Sequencer};
+
+pub mod session;
+pub use session::{ConnectEvents, DisconnectEvents, LimitAction, SessionLimits, SessionRegistry};
 
 pub mod target;
-pub use target::{AuthTarget, CorrelationId, SessionId, Target, Targets, UserId, BotId, UserPool, Uuid, ANON_USER_ID};
+pub use target::{AuthTarget, CorrelationId, GroupId, SessionId, Target, Targets, UserId, BotId, UserPool, Uuid, ANON_USER_ID};
+
+pub mod timesync;
+pub use timesync::{TimePing, TimePong, TimeSample, TimeSync};
 pub use wire_macros::WireObj;
 
 #[cfg(feature = "i18n")]
 pub mod i18n;
 
+#[cfg(feature = "bevy")]
+pub mod bevy;
+
+// allows generated code to refer to `::wire` from within the crate itself
+extern crate self as wire;
+
+#[doc(hidden)]
+pub mod __private {
+	#[cfg(feature = "bevy")]
+	pub use bevy_app;
+	#[cfg(feature = "bevy")]
+	pub use bevy_ecs;
+	pub use serde;
+}
+
 #[cfg(test)]
 mod tests {
 	use super::*;
 
+	#[derive(WireObj)]
+	#[rustfmt::ignore]
+	#[derive(Clone, Debug, PartialEq, Eq)]
+	#[wire(module = "actions")]
+	enum Action {
+		Join { room: u32 },
+		Leave,
+	}
+
+	#[derive(WireObj)]
+	#[rustfmt::ignore]
+	#[derive(Clone, Debug, PartialEq, Eq)]
+	#[wire(module = "replies", reexport)]
+	enum Event {
+		Join { room: u32, user: UserId },
+		Left(UserId),
+	}
+
 	#[test]
 	#[rustfmt::skip]
 	fn test_wire_obj() {
@@ -67,4 +144,114 @@ mod tests {
 		println!("{:?}", foo_a.clone());
 		println!("{:?}", foo_b.clone());
 	}
+
+	#[test]
+	#[rustfmt::skip]
+	fn test_wire_obj_conversions() {
+		#[derive(WireObj)]
+		#[rustfmt::ignore]
+		#[derive(Clone, Debug, PartialEq, Eq)]
+		enum Bar {
+			Tuple(u32, String),
+			Unit,
+		}
+
+		assert_eq!(Bar::from(Tuple(1, "a".to_string())), Bar::Tuple(1, "a".to_string()));
+		assert_eq!(Bar::from(Unit), Bar::Unit);
+		assert_eq!(Unit::try_from(Bar::Unit), Ok(Unit));
+		assert_eq!(Tuple::try_from(Bar::Unit), Err(Bar::Unit));
+		assert_eq!(Tuple::try_from(Bar::Tuple(1, "a".to_string())), Ok(Tuple(1, "a".to_string())));
+		let req = Req::<Bar>::new(Target::new_anon(1), Unit, CorrelationId::nil());
+		assert_eq!(req.action, Bar::Unit);
+	}
+
+	#[test]
+	#[rustfmt::skip]
+	fn test_wire_obj_kind() {
+		#[derive(WireObj)]
+		#[rustfmt::ignore]
+		#[derive(Clone, Debug, PartialEq, Eq)]
+		enum Baz {
+			First { a: i32 },
+			Second(u32),
+			Third,
+		}
+
+		assert_eq!(Baz::First { a: 1 }.kind(), BazKind::First);
+		assert_eq!(Baz::Second(2).kind(), Second::KIND);
+		assert_eq!(BazKind::ALL, &[BazKind::First, BazKind::Second, BazKind::Third]);
+		assert_eq!(BazKind::Third.to_string(), "Third");
+		assert_eq!("Second".parse::<BazKind>(), Ok(BazKind::Second));
+		assert_eq!("Fourth".parse::<BazKind>(), Err(ParseKindError("Fourth".to_string())));
+	}
+
+	#[test]
+	#[rustfmt::skip]
+	fn test_wire_obj_generics() {
+		trait Ruleset {
+			type Move: Clone + std::fmt::Debug + PartialEq;
+		}
+
+		#[derive(Clone, Debug, PartialEq)]
+		struct Chess;
+
+		impl Ruleset for Chess {
+			type Move = (u8, u8);
+		}
+
+		#[derive(WireObj)]
+		#[rustfmt::ignore]
+		#[derive(Clone, Debug, PartialEq)]
+		enum Game<'a, R, const N: usize>
+		where
+			R: Ruleset,
+		{
+			Play { mv: R::Move },
+			Chat(&'a str),
+			Board([u8; N]),
+			Resign,
+		}
+
+		let play = Play::<Chess> { mv: (1, 2) };
+		assert_eq!(Game::<'_, Chess, 4>::from(play.clone()), Game::Play { mv: (1, 2) });
+		let game: Game<'_, Chess, 4> = Game::Play { mv: (1, 2) };
+		assert_eq!(Play::try_from(game), Ok(play));
+		assert_eq!(Game::<'_, Chess, 4>::from(Chat("gg")), Game::Chat("gg"));
+		assert_eq!(Game::<'_, Chess, 2>::from(Board([1, 2])).kind(), Board::<2>::KIND);
+		assert_eq!(Game::<'_, Chess, 2>::from(Resign).kind(), GameKind::Resign);
+	}
+
+	#[test]
+	#[rustfmt::skip]
+	fn test_wire_obj_attributes() {
+		#[derive(WireObj)]
+		#[rustfmt::ignore]
+		#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+		#[serde(tag = "type", content = "data")]
+		#[wire(exclude(serde))]
+		enum Qux {
+			#[wire(rename = "JoinRoom", derive(Default))]
+			Join { room: u32 },
+			#[wire(skip)]
+			Leave,
+			#[wire(exclude(derive), attr(derive(Debug)))]
+			Say(#[wire(attr(allow(dead_code)))] String),
+		}
+
+		struct Leave;
+
+		assert_eq!(Qux::from(JoinRoom::default()), Qux::Join { room: 0 });
+		assert_eq!(JoinRoom::KIND, QuxKind::Join);
+		assert_eq!(QuxKind::ALL, &[QuxKind::Join, QuxKind::Leave, QuxKind::Say]);
+		assert_eq!(format!("{:?}", Say("hi".to_string())), r#"Say("hi")"#);
+		let _ = Leave;
+	}
+
+	#[test]
+	fn test_wire_obj_module() {
+		assert_eq!(Action::from(actions::Join { room: 1 }), Action::Join { room: 1 });
+		assert_eq!(actions::Leave::KIND, ActionKind::Leave);
+		assert_eq!(Event::from(replies::Join { room: 1, user: ANON_USER_ID }), Event::Join { room: 1, user: ANON_USER_ID });
+		assert_eq!(Left::try_from(Event::Left(ANON_USER_ID)), Ok(Left(ANON_USER_ID)));
+	}
 }
@@ -6,6 +6,7 @@ use crate::*;
 #[derive(serde::Serialize, serde::Deserialize)]
 pub struct Req<A> {
 	/// The target that sent the request.
+	#[serde(with = "crate::compact")]
 	pub from: Target,
 	/// The action that the target wants to perform.
 	pub action: A,
@@ -1,14 +1,22 @@
 //! Common utilities for responses.
 
-use crate::Targets;
+use std::sync::Arc;
+
+use crate::{Clock, Codec, CodecError, CorrelationId, Destination, Frame, Req, Resolver, Sequenced, Sequencer, SessionTable, SystemClock, Targets};
 
 /// An event that occurred in the system directed towards a particular [`Targets`].
+///
+/// Optionally carries the correlation ID of the request it is a direct reply to.
 #[derive(serde::Serialize, serde::Deserialize)]
 pub struct Res<E> {
 	/// The targets that this event is sent to.
+	#[serde(with = "crate::compact")]
 	pub targets: Targets,
 	/// The event that occurred.
 	pub event: TimestampedEvent<E>,
+	/// The correlation ID of the request this event is a reply to.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub corrid: Option<CorrelationId>,
 }
 
 impl<E> Res<E> {
@@ -17,8 +25,79 @@ impl<E> Res<E> {
 		Self {
 			targets: targets.into(),
 			event: TimestampedEvent::new(event),
+			corrid: None,
+		}
+	}
+
+	/// Creates a new response with an event that occurred at the given time (in ms).
+	pub fn new_at(targets: impl Into<Targets>, event: impl Into<E>, timestamp: i64) -> Self {
+		Self {
+			targets: targets.into(),
+			event: TimestampedEvent::new_at(event, timestamp),
+			corrid: None,
+		}
+	}
+
+	/// Creates a new response directed to the sender of the request, correlated to it.
+	pub fn reply_to<A>(req: &Req<A>, event: impl Into<E>) -> Self {
+		Self {
+			targets: req.from.into(),
+			event: TimestampedEvent::new(event),
+			corrid: Some(req.corrid),
 		}
 	}
+
+	/// Correlates the response to a request.
+	pub fn with_corrid(mut self, corrid: CorrelationId) -> Self {
+		self.corrid = Some(corrid);
+		self
+	}
+}
+
+impl<E> Res<E>
+where
+	E: serde::Serialize,
+{
+	/// Encodes the response as a [`Frame::Response`] once, so that the bytes can be shared across all recipients.
+	///
+	/// The targets are kept aside for the resolution step and not encoded.
+	pub fn into_encoded<C>(self, codec: &C) -> Result<EncodedRes, CodecError>
+	where
+		C: Codec,
+	{
+		let Self { targets, event, corrid } = self;
+		// the representation of a response frame does not depend on the action and error types
+		let frame = Frame::<(), E, ()>::Response(Delivered { event, corrid, seq: None });
+		let bytes = codec.encode(&frame)?;
+
+		Ok(EncodedRes {
+			targets,
+			bytes: bytes.into(),
+		})
+	}
+}
+
+impl<E> Res<E>
+where
+	E: Clone,
+{
+	/// Fans the response out to every resolved recipient, stripping the targets.
+	///
+	/// Every recipient gets its own copy of the event, see [`Res::into_encoded`] for sharing a single encoding
+	/// (or use an `Arc`-backed event).
+	pub fn fan_out<'a, T>(&'a self, resolver: &Resolver<'a, T>) -> impl Iterator<Item = (Destination, Delivered<E>)> + 'a
+	where
+		T: SessionTable + ?Sized,
+	{
+		resolver.resolve(&self.targets).map(|destination| {
+			let delivered = Delivered {
+				event: self.event.clone(),
+				corrid: self.corrid,
+				seq: None,
+			};
+			(destination, delivered)
+		})
+	}
 }
 
 impl<E> PartialEq for Res<E>
@@ -26,7 +105,7 @@ where
 	E: PartialEq,
 {
 	fn eq(&self, other: &Self) -> bool {
-		self.targets == other.targets && self.event == other.event
+		self.targets == other.targets && self.event == other.event && self.corrid == other.corrid
 	}
 }
 
@@ -37,7 +116,11 @@ where
 	E: std::fmt::Debug,
 {
 	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-		f.debug_struct("Res").field("targets", &self.targets).field("event", &self.event).finish()
+		f.debug_struct("Res")
+			.field("targets", &self.targets)
+			.field("event", &self.event)
+			.field("corrid", &self.corrid)
+			.finish()
 	}
 }
 
@@ -49,6 +132,7 @@ where
 		Self {
 			targets: self.targets.clone(),
 			event: self.event.clone(),
+			corrid: self.corrid,
 		}
 	}
 }
@@ -59,6 +143,118 @@ impl<E> Into<Vec<Res<E>>> for Res<E> {
 	}
 }
 
+/// The client-facing projection of a [`Res`], as delivered to a single recipient.
+///
+/// The targets of a response are a server-internal routing detail (and would leak other users' IDs),
+/// so they are stripped before the response is sent to a client.
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct Delivered<E> {
+	/// The event that occurred.
+	pub event: TimestampedEvent<E>,
+	/// The correlation ID of the request this event is a reply to.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub corrid: Option<CorrelationId>,
+	/// The per-session sequence number of the event, see [`Sequencer`].
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub seq: Option<u64>,
+}
+
+impl<E> Delivered<E> {
+	/// Sets the per-session sequence number of the event.
+	pub fn with_seq(mut self, seq: u64) -> Self {
+		self.seq = Some(seq);
+		self
+	}
+}
+
+impl<E> From<Res<E>> for Delivered<E> {
+	fn from(value: Res<E>) -> Self {
+		Self {
+			event: value.event,
+			corrid: value.corrid,
+			seq: None,
+		}
+	}
+}
+
+impl<E> PartialEq for Delivered<E>
+where
+	E: PartialEq,
+{
+	fn eq(&self, other: &Self) -> bool {
+		self.event == other.event && self.corrid == other.corrid && self.seq == other.seq
+	}
+}
+
+impl<E> Eq for Delivered<E> where E: Eq {}
+
+impl<E> std::fmt::Debug for Delivered<E>
+where
+	E: std::fmt::Debug,
+{
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.debug_struct("Delivered")
+			.field("event", &self.event)
+			.field("corrid", &self.corrid)
+			.field("seq", &self.seq)
+			.finish()
+	}
+}
+
+impl<E> Clone for Delivered<E>
+where
+	E: Clone,
+{
+	fn clone(&self) -> Self {
+		Self {
+			event: self.event.clone(),
+			corrid: self.corrid,
+			seq: self.seq,
+		}
+	}
+}
+
+/// A response whose client-facing frame was encoded once and is shared across all recipients.
+///
+/// Created by [`Res::into_encoded`]. As the bytes are shared, they carry no per-session sequence number,
+/// use [`EncodedRes::fan_out_sequenced`] to wrap them into a [`Frame::Sequenced`] carrying one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EncodedRes {
+	/// The targets that this event is sent to.
+	pub targets: Targets,
+	/// The encoded [`Frame::Response`].
+	pub bytes: Arc<[u8]>,
+}
+
+impl EncodedRes {
+	/// Fans the encoded response out to every resolved recipient without copying the bytes.
+	pub fn fan_out<'a, T>(&'a self, resolver: &Resolver<'a, T>) -> impl Iterator<Item = (Destination, Arc<[u8]>)> + 'a
+	where
+		T: SessionTable + ?Sized,
+	{
+		resolver.resolve(&self.targets).map(|destination| (destination, self.bytes.clone()))
+	}
+
+	/// Fans the encoded response out to every resolved recipient without copying the bytes,
+	/// stamping each with the recipient's next sequence number.
+	///
+	/// The codec must be the one the response was encoded with.
+	pub fn fan_out_sequenced<'a, T, C>(
+		&'a self,
+		resolver: &Resolver<'a, T>,
+		sequencer: &'a mut Sequencer,
+		codec: &'a C,
+	) -> impl Iterator<Item = Result<(Destination, Sequenced), CodecError>> + 'a
+	where
+		T: SessionTable + ?Sized,
+		C: Codec,
+	{
+		resolver
+			.resolve(&self.targets)
+			.map(|destination| Ok((destination, sequencer.sequence_encoded(destination, codec, &self.bytes)?)))
+	}
+}
+
 /// A timestamped event that occurred in the system directed towards a particular [`Targets`].
 #[derive(serde::Serialize, serde::Deserialize)]
 pub struct TimestampedEvent<E> {
@@ -69,10 +265,20 @@ pub struct TimestampedEvent<E> {
 }
 
 impl<E> TimestampedEvent<E> {
-	/// Creates a new event.
+	/// Creates a new event timestamped by the system clock.
 	pub fn new(event: impl Into<E>) -> Self {
+		Self::with_clock(event, SystemClock)
+	}
+
+	/// Creates a new event timestamped by the given clock.
+	pub fn with_clock(event: impl Into<E>, clock: impl Clock) -> Self {
+		Self::new_at(event, clock.now_millis())
+	}
+
+	/// Creates a new event that occurred at the given time (in ms).
+	pub fn new_at(event: impl Into<E>, timestamp: i64) -> Self {
 		Self {
-			timestamp: chrono::Utc::now().timestamp_millis(),
+			timestamp,
 			event: event.into(),
 		}
 	}
@@ -83,10 +289,7 @@ where
 	E: Default,
 {
 	fn default() -> Self {
-		Self {
-			timestamp: chrono::Utc::now().timestamp_millis(),
-			event: Default::default(),
-		}
+		Self::with_clock(E::default(), SystemClock)
 	}
 }
 
@@ -124,3 +327,96 @@ where
 		}
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::*;
+
+	#[test]
+	fn test_fan_out() {
+		let mut registry = SessionRegistry::new();
+		let (alice, bob) = (UserId::new_v4(), UserId::new_v4());
+		let first = registry.connect::<()>(alice).unwrap().connected.session_id;
+		let second = registry.connect::<()>(bob).unwrap().connected.session_id;
+
+		let req = Req::<u32>::new(Target::new_auth_specific(alice, first), 1u32, CorrelationId::new_v4());
+		let res = Res::<u32>::new(req.from | Target::new_auth(bob), 2u32).with_corrid(req.corrid);
+		let resolver = Resolver::new(&registry);
+		let delivered = res.fan_out(&resolver).collect::<Vec<_>>();
+		assert_eq!(delivered.len(), 2);
+		assert_eq!(delivered[0].0, Destination::Session(alice, first));
+		assert_eq!(delivered[1].0, Destination::Session(bob, second));
+		assert!(delivered.iter().all(|(_, delivered)| delivered.event.event == 2 && delivered.corrid == Some(req.corrid)));
+		assert_eq!(Delivered::from(res.clone()), delivered[0].1);
+	}
+
+	#[test]
+	#[cfg(feature = "json")]
+	fn test_encoded_fan_out() {
+		use crate::codec::Json;
+
+		let mut registry = SessionRegistry::new();
+		let alice = UserId::new_v4();
+		registry.connect::<()>(alice).unwrap();
+		registry.connect::<()>(alice).unwrap();
+
+		let res = Res::<u32>::new(Target::new_auth(alice), 7u32);
+		let delivered = Delivered::from(res.clone());
+		let encoded = res.into_encoded(&Json).unwrap();
+		let resolver = Resolver::new(&registry);
+		let fanned = encoded.fan_out(&resolver).collect::<Vec<_>>();
+		assert_eq!(fanned.len(), 2);
+		assert!(Arc::ptr_eq(&fanned[0].1, &fanned[1].1));
+
+		let frame = Json.decode::<Frame<String, u32, NetworkError>>(&fanned[0].1).unwrap();
+		assert_eq!(frame, Frame::Response(delivered.clone()));
+
+		let mut sequencer = Sequencer::new();
+		let first = encoded.fan_out_sequenced(&resolver, &mut sequencer, &Json).collect::<Result<Vec<_>, _>>().unwrap();
+		let second = encoded.fan_out_sequenced(&resolver, &mut sequencer, &Json).collect::<Result<Vec<_>, _>>().unwrap();
+		assert_eq!(first.iter().chain(&second).map(|(_, sequenced)| sequenced.seq).collect::<Vec<_>>(), vec![0, 0, 1, 1]);
+		assert!(Arc::ptr_eq(&first[0].1.bytes, &second[1].1.bytes));
+
+		// sequenced shared frames are interleaved with plain frames on one stream
+		let corrid = CorrelationId::new_v4();
+		let error = Error::new(Target::new_auth(alice), NetworkError::InvalidMessage, corrid);
+		let direct = Delivered::from(Res::<u32>::new(Target::new_auth(alice), 8u32)).with_seq(2);
+		let stream = vec![
+			Json.encode(&Frame::<String, u32, NetworkError>::Pong(1)).unwrap(),
+			first[0].1.to_vec(),
+			Json.encode(&Frame::<String, u32, NetworkError>::from(error.clone())).unwrap(),
+			second[0].1.to_vec(),
+			Json.encode(&Frame::<String, u32, NetworkError>::from(direct.clone())).unwrap(),
+			Json.encode(&Frame::<String, u32, NetworkError>::Close).unwrap(),
+		];
+
+		let mut tracker = SeqTracker::new();
+		let mut received = Vec::new();
+		for message in &stream {
+			match Json.decode::<Frame<String, u32, NetworkError>>(message).unwrap() {
+				Frame::Sequenced { seq, frame } => {
+					assert_eq!(tracker.track_seq(seq), Ok(()));
+					received.push(*frame);
+				},
+				Frame::Response(delivered) => {
+					assert_eq!(tracker.track(&delivered), Ok(()));
+					received.push(Frame::Response(delivered));
+				},
+				frame => received.push(frame),
+			}
+		}
+		assert_eq!(
+			received,
+			vec![
+				Frame::Pong(1),
+				Frame::Response(delivered.clone()),
+				Frame::Error(error),
+				Frame::Response(delivered),
+				Frame::Response(direct),
+				Frame::Close,
+			]
+		);
+		assert_eq!(tracker.expected(), 3);
+	}
+}
@@ -0,0 +1,581 @@
+//! Common utilities for resolving [`Targets`] into concrete destinations.
+
+use std::{borrow::BorrowMut, collections::HashSet};
+
+use crate::*;
+
+/// A concrete destination of a message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Destination {
+	/// A live session of a user (or an anonymous session if `user_id == ANON_USER_ID`).
+	Session(UserId, SessionId),
+	/// A bot.
+	Bot(BotId),
+}
+
+impl From<Destination> for Target {
+	fn from(value: Destination) -> Self {
+		match value {
+			Destination::Session(user_id, session_id) => Self::new_deduced(user_id, session_id),
+			Destination::Bot(bot_id) => Self::new_bot(bot_id),
+		}
+	}
+}
+
+impl Target {
+	/// Checks whether a message sent to the target is delivered to the destination.
+	pub fn covers(&self, destination: &Destination) -> bool {
+		match (self, destination) {
+			(Self::Anon(a), Destination::Session(user_id, b)) => *user_id == ANON_USER_ID && a == b,
+			(Self::Auth(AuthTarget::All(a)), Destination::Session(b, _)) => a == b,
+			(Self::Auth(AuthTarget::Specific(a, a_session)), Destination::Session(b, b_session)) => a == b && a_session == b_session,
+			(Self::Bot(a), Destination::Bot(b)) => a == b,
+			_ => false,
+		}
+	}
+}
+
+/// A table of live sessions that targets are resolved against.
+///
+/// Implemented by [`SessionRegistry`].
+pub trait SessionTable {
+	/// The iterator over all live sessions.
+	type Sessions<'a>: Iterator<Item = (UserId, SessionId)>
+	where
+		Self: 'a;
+	/// The iterator over the live sessions of a single user.
+	type UserSessions<'a>: Iterator<Item = SessionId>
+	where
+		Self: 'a;
+
+	/// Returns all live sessions.
+	fn sessions(&self) -> Self::Sessions<'_>;
+
+	/// Returns the live sessions of an authenticated user.
+	fn user_sessions(&self, user_id: UserId) -> Self::UserSessions<'_>;
+
+	/// Checks whether the session of the user is live.
+	fn contains(&self, user_id: UserId, session_id: SessionId) -> bool;
+}
+
+/// A resolver of [`Targets`] into concrete [`Destination`]s.
+///
+/// Only live sessions are yielded and every destination is yielded at most once, even if it is covered
+/// by several targets. Bots are not tracked by the session table, so bot targets are always yielded,
+/// while [`Targets::All`] only covers sessions. Groups are resolved into their members only if
+/// a [`GroupRegistry`] is provided, see [`Resolver::with_groups`]. Excluded targets are never yielded.
+///
+/// Set expressions ([`Targets::Except`], [`Targets::Union`] and [`Targets::Intersection`]) are resolved as well.
+pub struct Resolver<'a, T: ?Sized> {
+	table: &'a T,
+	groups: Option<&'a GroupRegistry>,
+}
+
+impl<'a, T> Resolver<'a, T>
+where
+	T: SessionTable + ?Sized,
+{
+	/// Creates a new resolver over a live session table.
+	pub fn new(table: &'a T) -> Self {
+		Self { table, groups: None }
+	}
+
+	/// Creates a new resolver over a live session table, resolving groups against a group registry.
+	pub fn with_groups(table: &'a T, groups: &'a GroupRegistry) -> Self {
+		Self { table, groups: Some(groups) }
+	}
+
+	/// Resolves the targets into their destinations.
+	///
+	/// Other targets than [`Targets::All`] keep a set of the yielded destinations (and compile their set expressions
+	/// into sets) to check every candidate in constant time, which allocates on every call.
+	/// Use [`Resolver::resolve_with`] on hot paths to reuse the allocations instead.
+	pub fn resolve(&self, targets: &'a Targets) -> Resolve<'a, T> {
+		Resolve::new(*self, targets, ResolveScratch::new())
+	}
+
+	/// Resolves the targets into their destinations, reusing the buffers of earlier resolves.
+	///
+	/// Does not allocate once the buffers have grown to fit the targets.
+	pub fn resolve_with<'s>(&self, targets: &'a Targets, scratch: &'s mut ResolveScratch) -> Resolve<'a, T, &'s mut ResolveScratch> {
+		Resolve::new(*self, targets, scratch)
+	}
+
+	/// Checks whether the targets cover the destination.
+	///
+	/// Does not check whether the destination is live.
+	pub fn covers(&self, targets: &Targets, destination: &Destination) -> bool {
+		covers(targets, self.groups, destination)
+	}
+}
+
+impl<T: ?Sized> Clone for Resolver<'_, T> {
+	fn clone(&self) -> Self {
+		*self
+	}
+}
+
+impl<T: ?Sized> Copy for Resolver<'_, T> {}
+
+impl<T: ?Sized> std::fmt::Debug for Resolver<'_, T> {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.debug_struct("Resolver").finish_non_exhaustive()
+	}
+}
+
+/// An iterator over the destinations of some targets.
+///
+/// Created by [`Resolver::resolve`] and [`Resolver::resolve_with`].
+///
+/// Candidates are taken from the generating leaves of the targets (those which can add destinations,
+/// i.e. not the excluded or intersected-with ones) in order, and yielded if the whole targets cover them
+/// and they were not yielded before.
+pub struct Resolve<'a, T, S = ResolveScratch>
+where
+	T: SessionTable + ?Sized + 'a,
+{
+	table: &'a T,
+	groups: Option<&'a GroupRegistry>,
+	/// The resolved targets.
+	targets: &'a Targets,
+	/// Whether the candidates must be checked against the whole targets (i.e. they contain exclusions or intersections).
+	filtered: bool,
+	/// Whether every candidate is unique, so that the yielded destinations need not be remembered.
+	unique: bool,
+	/// The buffers of the yielded destinations and the compiled targets.
+	scratch: S,
+	/// The index of the next generating leaf to expand.
+	leaf: usize,
+	/// The progress within the generating leaf currently being expanded.
+	state: Option<State<'a, T>>,
+}
+
+/// The progress within a generating leaf of a [`Resolve`] iterator.
+enum State<'a, T>
+where
+	T: SessionTable + ?Sized + 'a,
+{
+	/// Yielding all live sessions.
+	All(T::Sessions<'a>),
+	/// Yielding the destinations of each target in turn.
+	Few {
+		/// The targets being resolved.
+		targets: &'a [Target],
+		/// The index of the next target to expand.
+		next: usize,
+		/// The sessions of the user target currently being expanded.
+		user: Option<(UserId, T::UserSessions<'a>)>,
+	},
+}
+
+impl<'a, T, S> Iterator for Resolve<'a, T, S>
+where
+	T: SessionTable + ?Sized + 'a,
+	S: BorrowMut<ResolveScratch>,
+{
+	type Item = Destination;
+
+	fn next(&mut self) -> Option<Self::Item> {
+		loop {
+			let state = match &mut self.state {
+				Some(state) => state,
+				None => {
+					let leaf = nth_leaf(self.targets, &mut self.leaf.clone())?;
+					self.leaf += 1;
+					self.state.insert(State::new(leaf, self.table, self.groups))
+				},
+			};
+
+			match state.next(self.table) {
+				Some(destination) if self.is_new(&destination) => return Some(destination),
+				Some(_) => continue,
+				None => self.state = None,
+			}
+		}
+	}
+}
+
+impl<'a, T, S> Resolve<'a, T, S>
+where
+	T: SessionTable + ?Sized + 'a,
+	S: BorrowMut<ResolveScratch>,
+{
+	/// Starts resolving the targets, clearing the scratch buffers.
+	fn new(resolver: Resolver<'a, T>, targets: &'a Targets, mut scratch: S) -> Self {
+		let filtered = is_filtered(targets);
+		let buffers = scratch.borrow_mut();
+		buffers.clear();
+		if filtered {
+			buffers.compile(targets, resolver.groups);
+		}
+
+		Self {
+			table: resolver.table,
+			groups: resolver.groups,
+			targets,
+			filtered,
+			unique: matches!(nth_leaf(targets, &mut 0), Some(Targets::All)) && nth_leaf(targets, &mut 1).is_none(),
+			scratch,
+			leaf: 0,
+			state: None,
+		}
+	}
+
+	/// Checks whether the destination of the current leaf is covered by the targets and was not yielded before.
+	fn is_new(&mut self, destination: &Destination) -> bool {
+		let scratch = self.scratch.borrow_mut();
+		(!self.filtered || scratch.covers(self.targets, &mut 0, destination)) && (self.unique || scratch.seen.insert(*destination))
+	}
+}
+
+impl<'a, T> State<'a, T>
+where
+	T: SessionTable + ?Sized + 'a,
+{
+	/// Starts expanding a generating leaf.
+	fn new(leaf: &'a Targets, table: &'a T, groups: Option<&'a GroupRegistry>) -> Self {
+		let targets = match leaf {
+			Targets::All => return Self::All(table.sessions()),
+			Targets::Few(targets) => targets,
+			Targets::Group(group_id) => groups.map_or(&[][..], |groups| groups.members(*group_id)),
+			_ => unreachable!("set expressions are not leaves"),
+		};
+
+		Self::Few {
+			targets,
+			next: 0,
+			user: None,
+		}
+	}
+
+	/// Yields the next live destination of the leaf.
+	fn next(&mut self, table: &'a T) -> Option<Destination> {
+		let (targets, next, user) = match self {
+			Self::All(sessions) => return sessions.next().map(|(user_id, session_id)| Destination::Session(user_id, session_id)),
+			Self::Few { targets, next, user } => (*targets, next, user),
+		};
+
+		loop {
+			if let Some((user_id, sessions)) = user {
+				match sessions.next() {
+					Some(session_id) => return Some(Destination::Session(*user_id, session_id)),
+					None => *user = None,
+				}
+			}
+
+			let target = targets.get(*next)?;
+			*next += 1;
+			let destination = match *target {
+				Target::Anon(session_id) => table.contains(ANON_USER_ID, session_id).then_some(Destination::Session(ANON_USER_ID, session_id)),
+				Target::Auth(AuthTarget::Specific(user_id, session_id)) => table.contains(user_id, session_id).then_some(Destination::Session(user_id, session_id)),
+				Target::Auth(AuthTarget::All(user_id)) => {
+					*user = Some((user_id, table.user_sessions(user_id)));
+					None
+				},
+				Target::Bot(bot_id) => Some(Destination::Bot(bot_id)),
+			};
+
+			if destination.is_some() {
+				return destination
+			}
+		}
+	}
+}
+
+impl<'a, T, S> std::fmt::Debug for Resolve<'a, T, S>
+where
+	T: SessionTable + ?Sized + 'a,
+{
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.debug_struct("Resolve").field("targets", &self.targets).finish_non_exhaustive()
+	}
+}
+
+/// Returns the `n`-th generating leaf of the targets, counting down `n` for every skipped leaf.
+fn nth_leaf<'a>(targets: &'a Targets, n: &mut usize) -> Option<&'a Targets> {
+	match targets {
+		Targets::All | Targets::Few(..) | Targets::Group(..) => {
+			if *n == 0 {
+				return Some(targets)
+			}
+			*n -= 1;
+			None
+		},
+		Targets::Except(inner, _) => nth_leaf(inner, n),
+		Targets::Union(targets) => targets.iter().find_map(|targets| nth_leaf(targets, n)),
+		Targets::Intersection(targets) => targets.first().and_then(|targets| nth_leaf(targets, n)),
+	}
+}
+
+/// Reusable buffers of a [`Resolve`] iterator, see [`Resolver::resolve_with`].
+#[derive(Debug, Default)]
+pub struct ResolveScratch {
+	/// The destinations yielded so far.
+	seen: HashSet<Destination>,
+	/// The sets of the compiled leaves of the targets, in the order they are walked.
+	sets: Vec<TargetSet>,
+	/// The number of sets in use.
+	used: usize,
+}
+
+impl ResolveScratch {
+	/// Creates new empty buffers.
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Clears the buffers, keeping their allocations.
+	fn clear(&mut self) {
+		self.seen.clear();
+		for set in &mut self.sets[..self.used] {
+			set.clear();
+		}
+		self.used = 0;
+	}
+
+	/// Compiles the leaves of the targets into sets, resolving groups against the group registry.
+	fn compile(&mut self, targets: &Targets, groups: Option<&GroupRegistry>) {
+		match targets {
+			Targets::All => {},
+			Targets::Few(targets) => self.push_set(targets),
+			Targets::Group(group_id) => self.push_set(groups.map_or(&[][..], |groups| groups.members(*group_id))),
+			Targets::Except(inner, excluded) => {
+				self.compile(inner, groups);
+				self.compile(excluded, groups);
+			},
+			Targets::Union(targets) | Targets::Intersection(targets) => {
+				for targets in targets {
+					self.compile(targets, groups);
+				}
+			},
+		}
+	}
+
+	/// Compiles a leaf into the next set.
+	fn push_set(&mut self, targets: &[Target]) {
+		if self.used == self.sets.len() {
+			self.sets.push(TargetSet::default());
+		}
+		self.sets[self.used].extend(targets);
+		self.used += 1;
+	}
+
+	/// Checks whether the compiled targets cover the destination, with one constant-time lookup per leaf.
+	///
+	/// Every leaf is visited, so that `next` stays in step with the order the sets were compiled in.
+	fn covers(&self, targets: &Targets, next: &mut usize, destination: &Destination) -> bool {
+		match targets {
+			Targets::All => matches!(destination, Destination::Session(..)),
+			Targets::Few(..) | Targets::Group(..) => {
+				*next += 1;
+				self.sets[*next - 1].covers(destination)
+			},
+			Targets::Except(inner, excluded) => {
+				let covered = self.covers(inner, next, destination);
+				!self.covers(excluded, next, destination) && covered
+			},
+			Targets::Union(targets) => {
+				let mut covered = false;
+				for targets in targets {
+					covered |= self.covers(targets, next, destination);
+				}
+				covered
+			},
+			Targets::Intersection(targets) => {
+				let mut covered = !targets.is_empty();
+				for targets in targets {
+					covered &= self.covers(targets, next, destination);
+				}
+				covered
+			},
+		}
+	}
+}
+
+/// A set of targets with constant-time lookups of the destinations they cover.
+#[derive(Debug, Default)]
+struct TargetSet {
+	/// The users whose every session is covered.
+	users: HashSet<UserId>,
+	/// The covered sessions (anonymous ones under [`ANON_USER_ID`]).
+	sessions: HashSet<(UserId, SessionId)>,
+	/// The covered bots.
+	bots: HashSet<BotId>,
+}
+
+impl TargetSet {
+	/// Adds the targets to the set.
+	fn extend<'a>(&mut self, targets: impl IntoIterator<Item = &'a Target>) {
+		for target in targets {
+			match *target {
+				Target::Anon(session_id) => self.sessions.insert((ANON_USER_ID, session_id)),
+				Target::Auth(AuthTarget::All(user_id)) => self.users.insert(user_id),
+				Target::Auth(AuthTarget::Specific(user_id, session_id)) => self.sessions.insert((user_id, session_id)),
+				Target::Bot(bot_id) => self.bots.insert(bot_id),
+			};
+		}
+	}
+
+	/// Removes all targets, keeping the allocations.
+	fn clear(&mut self) {
+		self.users.clear();
+		self.sessions.clear();
+		self.bots.clear();
+	}
+
+	/// Checks whether the set covers the destination.
+	fn covers(&self, destination: &Destination) -> bool {
+		match *destination {
+			Destination::Session(user_id, session_id) => self.users.contains(&user_id) || self.sessions.contains(&(user_id, session_id)),
+			Destination::Bot(bot_id) => self.bots.contains(&bot_id),
+		}
+	}
+}
+
+/// Checks whether the targets contain exclusions or intersections, which their generating leaves may not satisfy.
+fn is_filtered(targets: &Targets) -> bool {
+	match targets {
+		Targets::All | Targets::Few(..) | Targets::Group(..) => false,
+		Targets::Except(..) | Targets::Intersection(..) => true,
+		Targets::Union(targets) => targets.iter().any(is_filtered),
+	}
+}
+
+/// Checks whether the targets cover the destination, resolving groups against the group registry.
+///
+/// Scans the targets, which is cheaper than compiling them into sets for a single check.
+fn covers(targets: &Targets, groups: Option<&GroupRegistry>, destination: &Destination) -> bool {
+	match targets {
+		Targets::All => matches!(destination, Destination::Session(..)),
+		Targets::Few(targets) => targets.iter().any(|target| target.covers(destination)),
+		Targets::Group(group_id) => groups.is_some_and(|groups| groups.members(*group_id).iter().any(|target| target.covers(destination))),
+		Targets::Except(inner, excluded) => covers(inner, groups, destination) && !covers(excluded, groups, destination),
+		Targets::Union(targets) => targets.iter().any(|targets| covers(targets, groups, destination)),
+		Targets::Intersection(targets) => !targets.is_empty() && targets.iter().all(|targets| covers(targets, groups, destination)),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_resolver() {
+		let mut registry = SessionRegistry::new();
+		let (alice, bob, bot) = (UserId::new_v4(), UserId::new_v4(), BotId::new_v4());
+		let first = registry.connect::<()>(alice).unwrap().connected.session_id;
+		let second = registry.connect::<()>(alice).unwrap().connected.session_id;
+		let third = registry.connect::<()>(bob).unwrap().connected.session_id;
+		let anon = registry.connect::<()>(ANON_USER_ID).unwrap().connected.session_id;
+
+		let resolver = Resolver::new(&registry);
+		let all = resolver.resolve(&Targets::All).collect::<Vec<_>>();
+		assert_eq!(
+			all,
+			vec![
+				Destination::Session(alice, first),
+				Destination::Session(alice, second),
+				Destination::Session(bob, third),
+				Destination::Session(ANON_USER_ID, anon),
+			]
+		);
+
+		let targets = Targets::Few(vec![
+			Target::new_auth_specific(alice, second),
+			Target::new_auth(alice),
+			Target::new_anon(anon),
+			Target::new_anon(anon),
+			Target::new_anon(anon + 100),
+			Target::new_auth_specific(bob, first),
+			Target::new_bot(bot),
+			Target::new_auth(bob),
+			Target::new_auth_specific(bob, third),
+		]);
+		let few = resolver.resolve(&targets).collect::<Vec<_>>();
+		assert_eq!(
+			few,
+			vec![
+				Destination::Session(alice, second),
+				Destination::Session(alice, first),
+				Destination::Session(ANON_USER_ID, anon),
+				Destination::Bot(bot),
+				Destination::Session(bob, third),
+			]
+		);
+
+		let (lobby, empty) = (GroupId::new_v4(), GroupId::new_v4());
+		let mut groups = GroupRegistry::new();
+		groups.join(lobby, Target::new_auth(alice));
+		groups.join(lobby, Target::new_auth_specific(alice, first));
+		groups.join(lobby, Target::new_anon(anon));
+		let resolver = Resolver::with_groups(&registry, &groups);
+		let members = resolver.resolve(&Targets::Group(lobby)).collect::<Vec<_>>();
+		assert_eq!(
+			members,
+			vec![
+				Destination::Session(alice, first),
+				Destination::Session(alice, second),
+				Destination::Session(ANON_USER_ID, anon),
+			]
+		);
+		assert_eq!(resolver.resolve(&Targets::Group(empty)).count(), 0);
+		assert_eq!(Resolver::new(&registry).resolve(&Targets::Group(lobby)).count(), 0);
+
+		let others = Targets::Group(lobby).except(vec![Target::new_auth_specific(alice, first)]);
+		assert_eq!(resolver.resolve(&others).collect::<Vec<_>>(), vec![Destination::Session(alice, second), Destination::Session(ANON_USER_ID, anon)]);
+		let others = Targets::All.except(vec![Target::new_auth(alice)]).except(vec![Target::new_anon(anon)]);
+		assert_eq!(resolver.resolve(&others).collect::<Vec<_>>(), vec![Destination::Session(bob, third)]);
+
+		let everyone = Targets::Group(lobby) | (Targets::All - Target::new_auth(alice));
+		assert_eq!(
+			resolver.resolve(&everyone).collect::<Vec<_>>(),
+			vec![
+				Destination::Session(alice, first),
+				Destination::Session(alice, second),
+				Destination::Session(ANON_USER_ID, anon),
+				Destination::Session(bob, third),
+			]
+		);
+		let alices = Targets::Group(lobby) & (Target::new_auth(alice) | Target::new_bot(bot));
+		assert_eq!(resolver.resolve(&alices).collect::<Vec<_>>(), vec![Destination::Session(alice, first), Destination::Session(alice, second)]);
+	}
+
+	#[test]
+	fn test_resolver_exclusions() {
+		let mut registry = SessionRegistry::new();
+		let (alice, bob, bot) = (UserId::new_v4(), UserId::new_v4(), BotId::new_v4());
+		let first = registry.connect::<()>(alice).unwrap().connected.session_id;
+		let second = registry.connect::<()>(alice).unwrap().connected.session_id;
+		let third = registry.connect::<()>(bob).unwrap().connected.session_id;
+		let resolver = Resolver::new(&registry);
+
+		// excluding a user excludes all of their sessions
+		let targets = Targets::Few(vec![Target::new_auth_specific(alice, first), Target::new_auth(bob), Target::new_bot(bot)]).except(Target::new_auth(alice));
+		assert_eq!(resolver.resolve(&targets).collect::<Vec<_>>(), vec![Destination::Session(bob, third), Destination::Bot(bot)]);
+		assert_eq!(resolver.resolve(&Targets::All.except(Target::new_auth(alice))).collect::<Vec<_>>(), vec![Destination::Session(bob, third)]);
+
+		// an exclusion matching nothing changes nothing
+		let targets = Targets::All.except(Target::new_auth(UserId::new_v4())).except(Target::new_anon(first));
+		assert_eq!(resolver.resolve(&targets).count(), 3);
+
+		// nested exclusions over duplicated targets
+		let few = Targets::Few(vec![
+			Target::new_auth(alice),
+			Target::new_auth_specific(alice, second),
+			Target::new_auth_specific(bob, third),
+			Target::new_auth(alice),
+			Target::new_auth_specific(bob, third),
+		]);
+		let targets = few.except(Target::new_auth_specific(alice, first)).except(Targets::none());
+		assert_eq!(resolver.resolve(&targets).collect::<Vec<_>>(), vec![Destination::Session(alice, second), Destination::Session(bob, third)]);
+
+		// the buffers are reused across resolves
+		let mut scratch = ResolveScratch::new();
+		let broadcast = Targets::All.except(Target::new_auth_specific(alice, first));
+		for _ in 0..2 {
+			assert_eq!(resolver.resolve_with(&broadcast, &mut scratch).count(), 2);
+			assert_eq!(resolver.resolve_with(&targets, &mut scratch).collect::<Vec<_>>(), vec![Destination::Session(alice, second), Destination::Session(bob, third)]);
+		}
+		assert_eq!(scratch.sets.len(), 3);
+	}
+}
@@ -0,0 +1,410 @@
+//! Common utilities for resuming sessions after a reconnect.
+//!
+//! Every new session is issued a [`ResumeToken`]. When its connection drops, the session is suspended
+//! instead of disconnected, staying live (and targetable) for a grace period. Reconnecting with the token
+//! within the grace period resumes the session under the same session ID, replaying the events it missed
+//! from a bounded per-session buffer.
+
+use std::{
+	collections::{HashMap, VecDeque},
+	sync::Arc,
+	time::{Duration, Instant},
+};
+
+use crate::*;
+
+/// The resume token type.
+pub type ResumeToken = Uuid;
+
+/// The events produced by a resumed session.
+pub struct ResumeEvents<M, E> {
+	/// The user resumed the session.
+	pub resumed: Resumed<M>,
+	/// The new resume token of the session, replacing the used one.
+	pub token: ResumeToken,
+	/// The events the session missed, oldest first.
+	pub replay: Vec<Replay<E>>,
+}
+
+impl<M, E> std::fmt::Debug for ResumeEvents<M, E>
+where
+	E: std::fmt::Debug,
+{
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.debug_struct("ResumeEvents")
+			.field("resumed", &self.resumed)
+			.field("token", &self.token)
+			.field("replay", &self.replay)
+			.finish()
+	}
+}
+
+/// An event buffered for replay.
+pub enum Replay<E> {
+	/// An event delivered on its own.
+	Delivered(Delivered<E>),
+	/// A shared encoded frame (see [`EncodedRes`]).
+	Encoded(Arc<[u8]>),
+	/// A shared encoded frame wrapped along with its sequence number.
+	Sequenced(Sequenced),
+}
+
+impl<E> Replay<E> {
+	/// Returns the per-session sequence number of the event.
+	pub fn seq(&self) -> Option<u64> {
+		match self {
+			Self::Delivered(delivered) => delivered.seq,
+			Self::Encoded(..) => None,
+			Self::Sequenced(sequenced) => Some(sequenced.seq),
+		}
+	}
+}
+
+impl<E> From<Delivered<E>> for Replay<E> {
+	fn from(value: Delivered<E>) -> Self {
+		Self::Delivered(value)
+	}
+}
+
+impl<E> From<Sequenced> for Replay<E> {
+	fn from(value: Sequenced) -> Self {
+		Self::Sequenced(value)
+	}
+}
+
+impl<E> From<Arc<[u8]>> for Replay<E> {
+	fn from(value: Arc<[u8]>) -> Self {
+		Self::Encoded(value)
+	}
+}
+
+impl<E> PartialEq for Replay<E>
+where
+	E: PartialEq,
+{
+	fn eq(&self, other: &Self) -> bool {
+		match (self, other) {
+			(Self::Delivered(a), Self::Delivered(b)) => a == b,
+			(Self::Encoded(a), Self::Encoded(b)) => a == b,
+			(Self::Sequenced(a), Self::Sequenced(b)) => a == b,
+			_ => false,
+		}
+	}
+}
+
+impl<E> Eq for Replay<E> where E: Eq {}
+
+impl<E> std::fmt::Debug for Replay<E>
+where
+	E: std::fmt::Debug,
+{
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Self::Delivered(delivered) => f.debug_tuple("Delivered").field(delivered).finish(),
+			Self::Encoded(bytes) => f.debug_tuple("Encoded").field(&bytes.len()).finish(),
+			Self::Sequenced(sequenced) => f.debug_struct("Sequenced").field("seq", &sequenced.seq).field("bytes", &sequenced.bytes.len()).finish(),
+		}
+	}
+}
+
+impl<E> Clone for Replay<E>
+where
+	E: Clone,
+{
+	fn clone(&self) -> Self {
+		match self {
+			Self::Delivered(delivered) => Self::Delivered(delivered.clone()),
+			Self::Encoded(bytes) => Self::Encoded(bytes.clone()),
+			Self::Sequenced(sequenced) => Self::Sequenced(sequenced.clone()),
+		}
+	}
+}
+
+/// A resumable session.
+struct Resumable<E> {
+	/// The user ID of the session.
+	user_id: UserId,
+	/// The current resume token of the session.
+	token: ResumeToken,
+	/// The recently delivered events along with when they were recorded, oldest first.
+	buffer: VecDeque<(Instant, Replay<E>)>,
+	/// The instant the connection of the session dropped.
+	suspended_at: Option<Instant>,
+}
+
+/// A tracker of resumable sessions and their replay buffers.
+///
+/// Works alongside a [`SessionRegistry`]: suspended sessions stay registered until they expire.
+pub struct Resumer<E> {
+	sessions: HashMap<SessionId, Resumable<E>>,
+	tokens: HashMap<ResumeToken, SessionId>,
+	/// The maximum number of buffered events per session.
+	capacity: usize,
+	/// For how long a suspended session can be resumed.
+	grace: Duration,
+}
+
+impl<E> Resumer<E> {
+	/// Creates a new resumer buffering up to `capacity` events per session and keeping
+	/// suspended sessions resumable for `grace`.
+	pub fn new(capacity: usize, grace: Duration) -> Self {
+		Self {
+			sessions: HashMap::new(),
+			tokens: HashMap::new(),
+			capacity,
+			grace,
+		}
+	}
+
+	/// Makes a new session resumable, returning its resume token.
+	///
+	/// Sessions evicted by the connection are forgotten.
+	pub fn connect<M>(&mut self, events: &ConnectEvents<M>) -> ResumeToken {
+		for evicted in &events.evicted {
+			self.disconnect(&evicted.disconnected);
+		}
+
+		let token = ResumeToken::new_v4();
+		let resumable = Resumable {
+			user_id: events.connected.user_id,
+			token,
+			buffer: VecDeque::new(),
+			suspended_at: None,
+		};
+		if let Some(previous) = self.sessions.insert(events.connected.session_id, resumable) {
+			self.tokens.remove(&previous.token);
+		}
+		self.tokens.insert(token, events.connected.session_id);
+		token
+	}
+
+	/// Buffers an event delivered to the destination now.
+	///
+	/// See [`Resumer::record_at`].
+	pub fn record(&mut self, destination: Destination, event: impl Into<Replay<E>>) {
+		self.record_at(destination, event, Instant::now())
+	}
+
+	/// Buffers an event delivered to the destination at the given instant, dropping the oldest buffered event if full.
+	///
+	/// Accepts a [`Delivered`] event as well as a shared encoded frame, either a [`Sequenced`] one or bare bytes.
+	pub fn record_at(&mut self, destination: Destination, event: impl Into<Replay<E>>, now: Instant) {
+		let Destination::Session(user_id, session_id) = destination else { return };
+		let Some(resumable) = self.sessions.get_mut(&session_id).filter(|resumable| resumable.user_id == user_id) else { return };
+		if self.capacity == 0 {
+			return
+		}
+		if resumable.buffer.len() == self.capacity {
+			resumable.buffer.pop_front();
+		}
+		resumable.buffer.push_back((now, event.into()));
+	}
+
+	/// Suspends a session whose connection dropped.
+	///
+	/// See [`Resumer::suspend_at`].
+	pub fn suspend(&mut self, user_id: UserId, session_id: SessionId) -> Result<(), SessionError> {
+		self.suspend_at(user_id, session_id, Instant::now())
+	}
+
+	/// Suspends a session whose connection dropped at the given instant.
+	///
+	/// Events delivered to it from now on are buffered for replay.
+	pub fn suspend_at(&mut self, user_id: UserId, session_id: SessionId, now: Instant) -> Result<(), SessionError> {
+		let resumable = self.get_mut(user_id, session_id).ok_or(SessionError::NoSuchSession)?;
+		resumable.suspended_at.get_or_insert(now);
+		Ok(())
+	}
+
+	/// Resumes a session by its token.
+	///
+	/// See [`Resumer::resume_at`].
+	pub fn resume<M>(&mut self, token: ResumeToken, last_seq: Option<u64>) -> Result<ResumeEvents<M, E>, SessionError>
+	where
+		E: Clone,
+	{
+		self.resume_at(token, last_seq, Instant::now())
+	}
+
+	/// Resumes a session by its token at the given instant.
+	///
+	/// Replays the buffered events after the last sequence number the client received
+	/// (or those delivered since the suspension if the client did not track sequence numbers).
+	/// The used token is replaced by a new one.
+	///
+	/// A client may reconnect before its dropped connection is detected (and the session suspended).
+	/// Without a `last_seq` there is then no telling which events the client received, so none of the
+	/// events without a sequence number are replayed: clients should track sequence numbers to resume reliably.
+	///
+	/// Returns [`SessionError::NoSuchSession`] if the token is unknown or has expired.
+	pub fn resume_at<M>(&mut self, token: ResumeToken, last_seq: Option<u64>, now: Instant) -> Result<ResumeEvents<M, E>, SessionError>
+	where
+		E: Clone,
+	{
+		let session_id = *self.tokens.get(&token).ok_or(SessionError::NoSuchSession)?;
+		let grace = self.grace;
+		let resumable = self.sessions.get_mut(&session_id).expect("every token belongs to a session");
+		if resumable.suspended_at.is_some_and(|suspended_at| suspended_at + grace <= now) {
+			return Err(SessionError::NoSuchSession)
+		}
+
+		let suspended_at = resumable.suspended_at.take();
+		let missed = |recorded_at: &Instant, event: &Replay<E>| match (event.seq(), last_seq) {
+			(Some(seq), Some(last_seq)) => seq > last_seq,
+			_ => suspended_at.is_some_and(|suspended_at| *recorded_at >= suspended_at),
+		};
+		let replay = resumable
+			.buffer
+			.iter()
+			.filter(|(recorded_at, event)| missed(recorded_at, event))
+			.map(|(_, event)| event.clone())
+			.collect();
+
+		let new_token = ResumeToken::new_v4();
+		resumable.token = new_token;
+		let user_id = resumable.user_id;
+		self.tokens.remove(&token);
+		self.tokens.insert(new_token, session_id);
+
+		Ok(ResumeEvents {
+			resumed: Resumed::new(user_id, session_id),
+			token: new_token,
+			replay,
+		})
+	}
+
+	/// Disconnects all suspended sessions that can no longer be resumed by now.
+	///
+	/// See [`Resumer::expire_at`].
+	pub fn expire<M>(&mut self, registry: &mut SessionRegistry) -> Vec<DisconnectEvents<M>> {
+		self.expire_at(registry, Instant::now())
+	}
+
+	/// Disconnects all suspended sessions that can no longer be resumed by the given instant from the registry.
+	pub fn expire_at<M>(&mut self, registry: &mut SessionRegistry, now: Instant) -> Vec<DisconnectEvents<M>> {
+		let grace = self.grace;
+		let expired = self
+			.sessions
+			.iter()
+			.filter(|(_, resumable)| resumable.suspended_at.is_some_and(|suspended_at| suspended_at + grace <= now))
+			.map(|(session_id, resumable)| (resumable.user_id, *session_id))
+			.collect::<Vec<_>>();
+
+		expired
+			.into_iter()
+			.filter_map(|(user_id, session_id)| {
+				self.forget(session_id);
+				registry.disconnect(user_id, session_id).ok()
+			})
+			.collect()
+	}
+
+	/// Forgets a session that disconnected for good.
+	pub fn disconnect<M>(&mut self, event: &Disconnected<M>) {
+		if self.get_mut(event.user_id, event.session_id).is_some() {
+			self.forget(event.session_id);
+		}
+	}
+
+	/// Checks whether the session is suspended.
+	pub fn is_suspended(&self, user_id: UserId, session_id: SessionId) -> bool {
+		self.sessions
+			.get(&session_id)
+			.is_some_and(|resumable| resumable.user_id == user_id && resumable.suspended_at.is_some())
+	}
+
+	/// Returns the earliest instant a suspended session expires at.
+	///
+	/// Useful for scheduling the next call to [`Resumer::expire`].
+	pub fn next_deadline(&self) -> Option<Instant> {
+		self.sessions.values().filter_map(|resumable| resumable.suspended_at).min().map(|suspended_at| suspended_at + self.grace)
+	}
+
+	/// Returns the resumable session of the user.
+	fn get_mut(&mut self, user_id: UserId, session_id: SessionId) -> Option<&mut Resumable<E>> {
+		self.sessions.get_mut(&session_id).filter(|resumable| resumable.user_id == user_id)
+	}
+
+	/// Removes a session along with its token.
+	fn forget(&mut self, session_id: SessionId) {
+		if let Some(resumable) = self.sessions.remove(&session_id) {
+			self.tokens.remove(&resumable.token);
+		}
+	}
+}
+
+impl<E> std::fmt::Debug for Resumer<E> {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.debug_struct("Resumer")
+			.field("sessions", &self.sessions.keys().collect::<Vec<_>>())
+			.field("capacity", &self.capacity)
+			.field("grace", &self.grace)
+			.finish()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_resumer() {
+		let mut registry = SessionRegistry::new();
+		let mut resumer = Resumer::<u32>::new(2, Duration::from_secs(5));
+		let mut sequencer = Sequencer::new();
+		let alice = UserId::new_v4();
+		let connected = registry.connect::<()>(alice).unwrap();
+		let token = resumer.connect(&connected);
+		let session_id = connected.connected.session_id;
+		let destination = Destination::Session(alice, session_id);
+
+		let mut deliver = |resumer: &mut Resumer<u32>, event: u32| {
+			let delivered = sequencer.sequence(destination, Res::<u32>::new(Target::from(destination), event).into());
+			resumer.record(destination, delivered);
+		};
+		deliver(&mut resumer, 0);
+		deliver(&mut resumer, 1);
+		resumer.suspend(alice, session_id).unwrap();
+		assert!(resumer.is_suspended(alice, session_id));
+		deliver(&mut resumer, 2);
+
+		let resumed = resumer.resume::<()>(token, Some(0)).unwrap();
+		assert_eq!(resumed.resumed, Resumed::new(alice, session_id));
+		assert_eq!(resumed.replay.iter().map(Replay::seq).collect::<Vec<_>>(), vec![Some(1), Some(2)]);
+		assert!(!resumer.is_suspended(alice, session_id));
+		assert_eq!(resumer.resume::<()>(token, None).unwrap_err(), SessionError::NoSuchSession);
+
+		// shared encoded frames are buffered without copying them
+		let bytes = Arc::<[u8]>::from(&b"frame"[..]);
+		let recorded_at = Instant::now();
+		let sequenced = Sequenced {
+			seq: 3,
+			head: Vec::new(),
+			bytes: bytes.clone(),
+			tail: Vec::new(),
+		};
+		resumer.record_at(destination, sequenced.clone(), recorded_at);
+		resumer.record_at(destination, bytes.clone(), recorded_at);
+		let resumed = resumer.resume_at::<()>(resumed.token, Some(2), recorded_at).unwrap();
+		assert_eq!(resumed.replay, vec![Replay::Sequenced(sequenced)]);
+		let Replay::Sequenced(replayed) = &resumed.replay[0] else { unreachable!() };
+		assert!(Arc::ptr_eq(&replayed.bytes, &bytes));
+
+		// without a sequence number nor a detected drop nothing can be told missed
+		let resumed = resumer.resume_at::<()>(resumed.token, None, recorded_at).unwrap();
+		assert!(resumed.replay.is_empty());
+
+		let suspended_at = Instant::now();
+		resumer.suspend_at(alice, session_id, suspended_at).unwrap();
+		deliver(&mut resumer, 3);
+		let expired_at = suspended_at + Duration::from_secs(5);
+		assert_eq!(resumer.next_deadline(), Some(expired_at));
+		assert_eq!(resumer.resume_at::<()>(resumed.token, None, expired_at).unwrap_err(), SessionError::NoSuchSession);
+
+		let expired = resumer.expire_at::<()>(&mut registry, expired_at);
+		assert_eq!(expired.len(), 1);
+		assert_eq!(expired[0].last_disconnected, Some(LastDisconnected::new(alice, session_id)));
+		assert!(registry.is_empty());
+		assert_eq!(resumer.resume::<()>(resumed.token, None).unwrap_err(), SessionError::NoSuchSession);
+	}
+}
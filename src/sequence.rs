@@ -0,0 +1,238 @@
+//! Common utilities for sequencing delivered events.
+//!
+//! The server stamps every [`Delivered`] event with a per-session, monotonically increasing sequence number
+//! using a [`Sequencer`], while the client checks them with a [`SeqTracker`] to detect dropped or repeated events.
+//!
+//! Frames encoded once for all recipients (see [`EncodedRes`]) cannot carry a per-session sequence number,
+//! so they are wrapped into a [`Frame::Sequenced`] carrying it instead (see [`Sequenced`]).
+
+use std::{collections::HashMap, sync::Arc};
+
+use crate::*;
+
+/// A server-side allocator of per-session sequence numbers.
+///
+/// Sequence numbers of every destination start at 0.
+#[derive(Debug, Clone, Default)]
+pub struct Sequencer {
+	/// The next sequence number of every destination.
+	next: HashMap<Destination, u64>,
+}
+
+impl Sequencer {
+	/// Creates a new sequencer.
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Allocates the next sequence number of the destination.
+	pub fn next(&mut self, destination: Destination) -> u64 {
+		let next = self.next.entry(destination).or_default();
+		let seq = *next;
+		*next += 1;
+		seq
+	}
+
+	/// Stamps an event delivered to the destination with its next sequence number.
+	pub fn sequence<E>(&mut self, destination: Destination, delivered: Delivered<E>) -> Delivered<E> {
+		delivered.with_seq(self.next(destination))
+	}
+
+	/// Stamps a shared encoded frame delivered to the destination with its next sequence number.
+	///
+	/// The sequence number is only allocated if the frame could be wrapped.
+	pub fn sequence_encoded<C>(&mut self, destination: Destination, codec: &C, bytes: &Arc<[u8]>) -> Result<Sequenced, CodecError>
+	where
+		C: Codec,
+	{
+		let sequenced = Sequenced::new(codec, self.peek(&destination), bytes.clone())?;
+		self.next(destination);
+		Ok(sequenced)
+	}
+
+	/// Returns the sequence number the next event delivered to the destination will get.
+	pub fn peek(&self, destination: &Destination) -> u64 {
+		self.next.get(destination).copied().unwrap_or_default()
+	}
+
+	/// Forgets the sequence of the session that disconnected.
+	pub fn disconnect<M>(&mut self, event: &Disconnected<M>) {
+		self.next.remove(&Destination::Session(event.user_id, event.session_id));
+	}
+}
+
+/// A shared encoded frame wrapped into a [`Frame::Sequenced`] carrying the per-session sequence number of its recipient.
+///
+/// Sent as the [`Sequenced::head`], the shared bytes and the [`Sequenced::tail`], which together
+/// decode as a [`Frame::Sequenced`] like any other frame, so the shared bytes are never copied.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Sequenced {
+	/// The per-session sequence number.
+	pub seq: u64,
+	/// The encoded wrapping frame up to the shared frame.
+	pub head: Vec<u8>,
+	/// The shared encoded frame.
+	pub bytes: Arc<[u8]>,
+	/// The encoded wrapping frame after the shared frame.
+	pub tail: Vec<u8>,
+}
+
+impl Sequenced {
+	/// Wraps a shared frame encoded with the codec.
+	pub fn new<C>(codec: &C, seq: u64, bytes: Arc<[u8]>) -> Result<Self, CodecError>
+	where
+		C: Codec,
+	{
+		// a nested frame is encoded the same wherever it appears, so the shared frame takes the place of a placeholder one
+		let placeholder = codec.encode(&Frame::<(), (), ()>::Close)?;
+		let wrapped = codec.encode(&Frame::<(), (), ()>::Sequenced {
+			seq,
+			frame: Box::new(Frame::Close),
+		})?;
+		let Some(at) = wrapped.windows(placeholder.len()).rposition(|window| window == placeholder) else {
+			return Err(CodecError::Encode("the codec does not nest frames verbatim".to_string()))
+		};
+
+		Ok(Self {
+			seq,
+			head: wrapped[..at].to_vec(),
+			bytes,
+			tail: wrapped[at + placeholder.len()..].to_vec(),
+		})
+	}
+
+	/// Returns the slices to send in order, for transports supporting vectored writes.
+	pub fn slices(&self) -> [&[u8]; 3] {
+		[&self.head, &self.bytes, &self.tail]
+	}
+
+	/// Returns the whole wrapping frame, for transports that cannot send it as separate slices.
+	pub fn to_vec(&self) -> Vec<u8> {
+		self.slices().concat()
+	}
+}
+
+/// A client-side tracker of the sequence numbers of received events.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct SeqTracker {
+	/// The sequence number of the next expected event.
+	expected: u64,
+}
+
+impl SeqTracker {
+	/// Creates a new tracker expecting the first event (0).
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Creates a new tracker expecting the given sequence number next, e.g. after a resync.
+	pub fn starting_at(expected: u64) -> Self {
+		Self { expected }
+	}
+
+	/// Returns the sequence number of the next expected event.
+	pub fn expected(&self) -> u64 {
+		self.expected
+	}
+
+	/// Tracks a received event.
+	///
+	/// Events without a sequence number are not tracked, e.g. those of a shared encoded frame,
+	/// whose sequence number is tracked from its [`Frame::Sequenced`] via [`SeqTracker::track_seq`] instead.
+	pub fn track<E>(&mut self, delivered: &Delivered<E>) -> Result<(), SequenceError> {
+		match delivered.seq {
+			Some(seq) => self.track_seq(seq),
+			None => Ok(()),
+		}
+	}
+
+	/// Tracks a received sequence number.
+	///
+	/// After a gap the tracker continues after the received sequence number, so every gap is reported once.
+	pub fn track_seq(&mut self, seq: u64) -> Result<(), SequenceError> {
+		if seq < self.expected {
+			return Err(SequenceError::Duplicate(seq))
+		}
+
+		let Some(next) = seq.checked_add(1) else {
+			return Err(SequenceError::Overflow(seq))
+		};
+
+		let expected = self.expected;
+		self.expected = next;
+		if seq > expected {
+			return Err(SequenceError::Gap { expected, received: seq })
+		}
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_sequencing() {
+		let (alice, bob) = (Destination::Session(UserId::new_v4(), 1), Destination::Session(ANON_USER_ID, 2));
+		let mut sequencer = Sequencer::new();
+		let res = Res::<u32>::new(Targets::All, 1u32);
+		let first = sequencer.sequence(alice, res.clone().into());
+		let second = sequencer.sequence(alice, res.clone().into());
+		assert_eq!((first.seq, second.seq), (Some(0), Some(1)));
+		assert_eq!(sequencer.next(bob), 0);
+		assert_eq!(sequencer.peek(&alice), 2);
+
+		let mut tracker = SeqTracker::new();
+		assert_eq!(tracker.track(&first), Ok(()));
+		assert_eq!(tracker.track(&first), Err(SequenceError::Duplicate(0)));
+		assert_eq!(tracker.track(&Delivered::from(res)), Ok(()));
+		assert_eq!(tracker.track_seq(4), Err(SequenceError::Gap { expected: 1, received: 4 }));
+		assert_eq!(tracker.track_seq(5), Ok(()));
+		assert_eq!(tracker.track(&second), Err(SequenceError::Duplicate(1)));
+		assert_eq!(tracker.expected(), 6);
+
+		let mut tracker = SeqTracker::starting_at(u64::MAX - 1);
+		assert_eq!(tracker.track_seq(u64::MAX - 1), Ok(()));
+		assert_eq!(tracker.track_seq(u64::MAX), Err(SequenceError::Overflow(u64::MAX)));
+		assert_eq!(tracker.expected(), u64::MAX);
+
+	}
+
+	#[cfg(any(feature = "json", feature = "msgpack", feature = "cbor"))]
+	fn sequenced_roundtrip(codec: impl Codec) {
+		type TestFrame = Frame<(), String, ()>;
+
+		let frame = TestFrame::from(Res::<String>::new(Targets::All, "moved".to_string()).with_corrid(CorrelationId::new_v4()));
+		let bytes = Arc::<[u8]>::from(codec.encode(&frame).unwrap());
+		let destination = Destination::Session(ANON_USER_ID, 1);
+		let mut sequencer = Sequencer::new();
+		let mut sequenced = vec![sequencer.sequence_encoded(destination, &codec, &bytes).unwrap()];
+		sequenced.extend([255, 65_536, u64::MAX].map(|seq| Sequenced::new(&codec, seq, bytes.clone()).unwrap()));
+		assert_eq!(sequencer.peek(&destination), 1);
+
+		for sequenced in sequenced {
+			assert!(Arc::ptr_eq(&sequenced.bytes, &bytes));
+			let decoded = codec.decode::<TestFrame>(&sequenced.to_vec()).unwrap();
+			assert_eq!(decoded.corrid(), frame.corrid());
+			assert_eq!(decoded, TestFrame::Sequenced { seq: sequenced.seq, frame: Box::new(frame.clone()) });
+		}
+	}
+
+	#[test]
+	#[cfg(feature = "json")]
+	fn test_sequenced_json() {
+		sequenced_roundtrip(crate::codec::Json);
+	}
+
+	#[test]
+	#[cfg(feature = "msgpack")]
+	fn test_sequenced_msgpack() {
+		sequenced_roundtrip(crate::codec::MsgPack);
+	}
+
+	#[test]
+	#[cfg(feature = "cbor")]
+	fn test_sequenced_cbor() {
+		sequenced_roundtrip(crate::codec::Cbor);
+	}
+}
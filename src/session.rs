@@ -0,0 +1,489 @@
+//! Common utilities for tracking live sessions.
+
+use std::{
+	collections::{BTreeMap, HashMap},
+	net::{IpAddr, Ipv4Addr, Ipv6Addr},
+};
+
+use crate::*;
+
+/// The lifecycle events produced by a new session.
+pub struct ConnectEvents<M> {
+	/// The user connected.
+	pub connected: Connected<M>,
+	/// The user connected without having a previous session active.
+	pub first_connected: Option<FirstConnected<M>>,
+	/// The sessions evicted to make room for the new session.
+	pub evicted: Vec<DisconnectEvents<M>>,
+}
+
+impl<M> std::fmt::Debug for ConnectEvents<M> {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.debug_struct("ConnectEvents")
+			.field("connected", &self.connected)
+			.field("first_connected", &self.first_connected)
+			.field("evicted", &self.evicted)
+			.finish()
+	}
+}
+
+/// The lifecycle events produced by a closed session.
+pub struct DisconnectEvents<M> {
+	/// The user disconnected.
+	pub disconnected: Disconnected<M>,
+	/// The user disconnected without having any other session active.
+	pub last_disconnected: Option<LastDisconnected<M>>,
+}
+
+impl<M> std::fmt::Debug for DisconnectEvents<M> {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.debug_struct("DisconnectEvents")
+			.field("disconnected", &self.disconnected)
+			.field("last_disconnected", &self.last_disconnected)
+			.finish()
+	}
+}
+
+/// What to do when a new session would exceed a session limit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum LimitAction {
+	/// Rejects the new session with [`SessionError::MaximumSessionsReached`].
+	#[default]
+	Reject,
+	/// Evicts the oldest session within the exceeded limit, emitting a [`Disconnected`] event for it.
+	EvictOldest,
+}
+
+/// The session limits enforced by a [`SessionRegistry`].
+///
+/// Anonymous sessions connected via [`SessionRegistry::connect_anon`] are bucketed by their IP address,
+/// masked to the configured prefix length (so that e.g. a whole IPv6 `/64` counts as one bucket).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SessionLimits {
+	/// The maximum number of live sessions in total.
+	pub global: Option<usize>,
+	/// The maximum number of live sessions of a single authenticated user.
+	pub per_user: Option<usize>,
+	/// The maximum number of live anonymous sessions within a single IP bucket.
+	pub per_anon_bucket: Option<usize>,
+	/// The prefix length of IPv4 anonymous buckets (lengths above 32 act as 32).
+	pub anon_ipv4_prefix: u8,
+	/// The prefix length of IPv6 anonymous buckets (lengths above 128 act as 128).
+	pub anon_ipv6_prefix: u8,
+	/// What to do when a limit is reached.
+	pub on_limit: LimitAction,
+}
+
+impl SessionLimits {
+	/// Creates unlimited session limits.
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Limits the number of live sessions in total.
+	pub fn with_global(mut self, max: usize) -> Self {
+		self.global = Some(max);
+		self
+	}
+
+	/// Limits the number of live sessions of a single authenticated user.
+	pub fn with_per_user(mut self, max: usize) -> Self {
+		self.per_user = Some(max);
+		self
+	}
+
+	/// Limits the number of live anonymous sessions within a single IP bucket.
+	pub fn with_per_anon_bucket(mut self, max: usize) -> Self {
+		self.per_anon_bucket = Some(max);
+		self
+	}
+
+	/// Sets the prefix lengths of anonymous IP buckets.
+	pub fn with_anon_prefixes(mut self, ipv4: u8, ipv6: u8) -> Self {
+		self.anon_ipv4_prefix = ipv4.min(32);
+		self.anon_ipv6_prefix = ipv6.min(128);
+		self
+	}
+
+	/// Sets what to do when a limit is reached.
+	pub fn on_limit(mut self, action: LimitAction) -> Self {
+		self.on_limit = action;
+		self
+	}
+
+	/// Returns the anonymous bucket of an IP address.
+	fn bucket(&self, addr: IpAddr) -> IpAddr {
+		match addr {
+			IpAddr::V4(addr) => {
+				let mask = u32::MAX.checked_shl(32u32.saturating_sub(self.anon_ipv4_prefix as u32)).unwrap_or(0);
+				IpAddr::V4(Ipv4Addr::from(u32::from(addr) & mask))
+			},
+			IpAddr::V6(addr) => {
+				let mask = u128::MAX.checked_shl(128u32.saturating_sub(self.anon_ipv6_prefix as u32)).unwrap_or(0);
+				IpAddr::V6(Ipv6Addr::from(u128::from(addr) & mask))
+			},
+		}
+	}
+}
+
+impl Default for SessionLimits {
+	fn default() -> Self {
+		Self {
+			global: None,
+			per_user: None,
+			per_anon_bucket: None,
+			anon_ipv4_prefix: 32,
+			anon_ipv6_prefix: 64,
+			on_limit: LimitAction::Reject,
+		}
+	}
+}
+
+/// A live session.
+#[derive(Debug, Clone, Copy)]
+struct Session {
+	/// The user ID of the session.
+	user_id: UserId,
+	/// The anonymous IP bucket of the session.
+	bucket: Option<IpAddr>,
+	/// The connection order of the session.
+	seq: u64,
+}
+
+/// A scope within which a session limit applies.
+#[derive(Debug, Clone, Copy)]
+enum LimitScope {
+	Global,
+	User(UserId),
+	Bucket(IpAddr),
+}
+
+/// A registry of live `(UserId, SessionId)` pairs.
+///
+/// Allocates session IDs, enforces [`SessionLimits`] and computes the lifecycle events of connecting
+/// and disconnecting sessions. Every anonymous session is treated as its own user, so it is always
+/// both the first and the last one.
+#[derive(Debug, Clone, Default)]
+pub struct SessionRegistry {
+	/// All live sessions.
+	sessions: HashMap<SessionId, Session>,
+	/// All live sessions in connection order.
+	order: BTreeMap<u64, SessionId>,
+	/// The live sessions of every authenticated user, oldest first.
+	users: HashMap<UserId, Vec<SessionId>>,
+	/// The live sessions of every anonymous IP bucket, oldest first.
+	buckets: HashMap<IpAddr, Vec<SessionId>>,
+	/// The enforced session limits.
+	limits: SessionLimits,
+	/// The next session ID to try to allocate.
+	next_session_id: SessionId,
+	/// The connection order of the next session.
+	next_seq: u64,
+}
+
+impl SessionRegistry {
+	/// Creates a new empty registry without session limits.
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Creates a new empty registry enforcing the given session limits.
+	pub fn with_limits(limits: SessionLimits) -> Self {
+		Self { limits, ..Default::default() }
+	}
+
+	/// Returns the enforced session limits.
+	pub fn limits(&self) -> &SessionLimits {
+		&self.limits
+	}
+
+	/// Registers a new session of a user (or an anonymous session if `user_id == ANON_USER_ID`).
+	///
+	/// Anonymous sessions registered this way are not subject to the per-bucket limit,
+	/// see [`SessionRegistry::connect_anon`].
+	pub fn connect<M>(&mut self, user_id: UserId) -> Result<ConnectEvents<M>, SessionError> {
+		self.connect_inner(user_id, None)
+	}
+
+	/// Registers a new anonymous session coming from the given IP address.
+	pub fn connect_anon<M>(&mut self, addr: IpAddr) -> Result<ConnectEvents<M>, SessionError> {
+		let bucket = self.limits.bucket(addr);
+		self.connect_inner(ANON_USER_ID, Some(bucket))
+	}
+
+	/// Registers a new session, enforcing the session limits.
+	fn connect_inner<M>(&mut self, user_id: UserId, bucket: Option<IpAddr>) -> Result<ConnectEvents<M>, SessionError> {
+		let scopes = [
+			(user_id != ANON_USER_ID).then_some(LimitScope::User(user_id)),
+			bucket.map(LimitScope::Bucket),
+			Some(LimitScope::Global),
+		];
+		let exceeded = scopes
+			.into_iter()
+			.flatten()
+			.filter(|scope| self.max(*scope).is_some_and(|max| self.count(*scope) >= max))
+			.collect::<Vec<_>>();
+
+		let evictable = exceeded.iter().all(|scope| self.oldest(*scope).is_some());
+		if !exceeded.is_empty() && (self.limits.on_limit == LimitAction::Reject || !evictable) {
+			return Err(SessionError::MaximumSessionsReached)
+		}
+
+		// the new session is inserted before evicting, so that evicting an older session of the same user
+		// is not reported as the user going offline
+		let session_id = self.allocate_session_id();
+		let first = self.insert(session_id, user_id, bucket);
+		let mut evicted = Vec::new();
+		for scope in exceeded {
+			while self.max(scope).is_some_and(|max| self.count(scope) > max) {
+				let Some((user_id, oldest)) = self.oldest(scope).filter(|(_, oldest)| *oldest != session_id) else { break };
+				let events = self.disconnect(user_id, oldest).expect("the oldest session is live");
+				evicted.push(events);
+			}
+		}
+
+		Ok(ConnectEvents {
+			connected: Connected::new(user_id, session_id),
+			first_connected: first.then(|| FirstConnected::new(user_id, session_id)),
+			evicted,
+		})
+	}
+
+	/// Removes a session of a user.
+	pub fn disconnect<M>(&mut self, user_id: UserId, session_id: SessionId) -> Result<DisconnectEvents<M>, SessionError> {
+		if !self.contains(user_id, session_id) {
+			return Err(SessionError::NoSuchSession)
+		}
+		let session = self.sessions.remove(&session_id).expect("the session is live");
+		self.order.remove(&session.seq);
+		if let Some(bucket) = session.bucket {
+			remove_from(&mut self.buckets, bucket, session_id);
+		}
+
+		let last = if user_id == ANON_USER_ID {
+			true
+		} else {
+			remove_from(&mut self.users, user_id, session_id)
+		};
+
+		Ok(DisconnectEvents {
+			disconnected: Disconnected::new(user_id, session_id),
+			last_disconnected: last.then(|| LastDisconnected::new(user_id, session_id)),
+		})
+	}
+
+	/// Checks whether the session of the user is live.
+	pub fn contains(&self, user_id: UserId, session_id: SessionId) -> bool {
+		self.sessions.get(&session_id).is_some_and(|session| session.user_id == user_id)
+	}
+
+	/// Returns the user ID owning the session.
+	pub fn user_of(&self, session_id: SessionId) -> Option<UserId> {
+		self.sessions.get(&session_id).map(|session| session.user_id)
+	}
+
+	/// Checks whether the user has any live session.
+	pub fn is_online(&self, user_id: UserId) -> bool {
+		self.users.contains_key(&user_id)
+	}
+
+	/// Returns all live sessions, oldest first.
+	pub fn sessions(&self) -> Sessions<'_> {
+		Sessions {
+			order: self.order.values(),
+			sessions: &self.sessions,
+		}
+	}
+
+	/// Returns the live sessions of an authenticated user, oldest first.
+	pub fn user_sessions(&self, user_id: UserId) -> std::iter::Copied<std::slice::Iter<'_, SessionId>> {
+		self.users.get(&user_id).map_or(&[][..], Vec::as_slice).iter().copied()
+	}
+
+	/// Returns the number of live sessions.
+	pub fn len(&self) -> usize {
+		self.sessions.len()
+	}
+
+	/// Checks whether there are no live sessions.
+	pub fn is_empty(&self) -> bool {
+		self.sessions.is_empty()
+	}
+
+	/// Inserts a new session, returning whether it is the first session of its user.
+	fn insert(&mut self, session_id: SessionId, user_id: UserId, bucket: Option<IpAddr>) -> bool {
+		let seq = self.next_seq;
+		self.next_seq += 1;
+		self.sessions.insert(session_id, Session { user_id, bucket, seq });
+		self.order.insert(seq, session_id);
+		if let Some(bucket) = bucket {
+			self.buckets.entry(bucket).or_default().push(session_id);
+		}
+
+		if user_id == ANON_USER_ID {
+			true
+		} else {
+			let sessions = self.users.entry(user_id).or_default();
+			sessions.push(session_id);
+			sessions.len() == 1
+		}
+	}
+
+	/// Returns the maximum number of live sessions within a limit scope.
+	fn max(&self, scope: LimitScope) -> Option<usize> {
+		match scope {
+			LimitScope::Global => self.limits.global,
+			LimitScope::User(..) => self.limits.per_user,
+			LimitScope::Bucket(..) => self.limits.per_anon_bucket,
+		}
+	}
+
+	/// Returns the number of live sessions within a limit scope.
+	fn count(&self, scope: LimitScope) -> usize {
+		match scope {
+			LimitScope::Global => self.sessions.len(),
+			LimitScope::User(user_id) => self.users.get(&user_id).map_or(0, Vec::len),
+			LimitScope::Bucket(bucket) => self.buckets.get(&bucket).map_or(0, Vec::len),
+		}
+	}
+
+	/// Returns the oldest live session within a limit scope.
+	fn oldest(&self, scope: LimitScope) -> Option<(UserId, SessionId)> {
+		let session_id = match scope {
+			LimitScope::Global => self.order.values().next(),
+			LimitScope::User(user_id) => self.users.get(&user_id).and_then(|sessions| sessions.first()),
+			LimitScope::Bucket(bucket) => self.buckets.get(&bucket).and_then(|sessions| sessions.first()),
+		}?;
+		Some((self.sessions[session_id].user_id, *session_id))
+	}
+
+	/// Allocates a session ID not used by any live session.
+	fn allocate_session_id(&mut self) -> SessionId {
+		while self.sessions.contains_key(&self.next_session_id) {
+			self.next_session_id = self.next_session_id.wrapping_add(1);
+		}
+		let session_id = self.next_session_id;
+		self.next_session_id = self.next_session_id.wrapping_add(1);
+		session_id
+	}
+}
+
+impl SessionTable for SessionRegistry {
+	type Sessions<'a> = Sessions<'a>;
+	type UserSessions<'a> = std::iter::Copied<std::slice::Iter<'a, SessionId>>;
+
+	fn sessions(&self) -> Self::Sessions<'_> {
+		self.sessions()
+	}
+
+	fn user_sessions(&self, user_id: UserId) -> Self::UserSessions<'_> {
+		self.user_sessions(user_id)
+	}
+
+	fn contains(&self, user_id: UserId, session_id: SessionId) -> bool {
+		self.contains(user_id, session_id)
+	}
+}
+
+/// An iterator over all live sessions of a [`SessionRegistry`], oldest first.
+#[derive(Debug, Clone)]
+pub struct Sessions<'a> {
+	order: std::collections::btree_map::Values<'a, u64, SessionId>,
+	sessions: &'a HashMap<SessionId, Session>,
+}
+
+impl Iterator for Sessions<'_> {
+	type Item = (UserId, SessionId);
+
+	fn next(&mut self) -> Option<Self::Item> {
+		self.order.next().map(|session_id| (self.sessions[session_id].user_id, *session_id))
+	}
+
+	fn size_hint(&self) -> (usize, Option<usize>) {
+		self.order.size_hint()
+	}
+}
+
+/// Removes a session from a keyed session list, returning whether the list became empty (and was removed).
+fn remove_from<K>(map: &mut HashMap<K, Vec<SessionId>>, key: K, session_id: SessionId) -> bool
+where
+	K: std::hash::Hash + Eq,
+{
+	let Some(sessions) = map.get_mut(&key) else { return false };
+	sessions.retain(|id| *id != session_id);
+	let empty = sessions.is_empty();
+	if empty {
+		map.remove(&key);
+	}
+	empty
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_session_registry() {
+		let mut registry = SessionRegistry::new();
+		let user_id = UserId::new_v4();
+
+		let first = registry.connect::<()>(user_id).unwrap();
+		assert!(first.first_connected.is_some());
+		let second = registry.connect::<()>(user_id).unwrap();
+		assert!(second.first_connected.is_none());
+		let anon = registry.connect::<()>(ANON_USER_ID).unwrap();
+		assert!(anon.first_connected.is_some());
+		assert_ne!(first.connected.session_id, second.connected.session_id);
+		assert_eq!(registry.user_sessions(user_id).count(), 2);
+		assert_eq!(registry.len(), 3);
+
+		let closed = registry.disconnect::<()>(user_id, first.connected.session_id).unwrap();
+		assert!(closed.last_disconnected.is_none());
+		assert_eq!(registry.disconnect::<()>(user_id, first.connected.session_id).unwrap_err(), SessionError::NoSuchSession);
+		assert_eq!(registry.disconnect::<()>(user_id, anon.connected.session_id).unwrap_err(), SessionError::NoSuchSession);
+
+		let closed = registry.disconnect::<()>(user_id, second.connected.session_id).unwrap();
+		assert_eq!(closed.last_disconnected, Some(LastDisconnected::new(user_id, second.connected.session_id)));
+		assert!(!registry.is_online(user_id));
+
+		let closed = registry.disconnect::<()>(ANON_USER_ID, anon.connected.session_id).unwrap();
+		assert!(closed.last_disconnected.is_some());
+		assert!(registry.is_empty());
+	}
+
+	#[test]
+	fn test_session_limits() {
+		let (alice, bob) = (UserId::new_v4(), UserId::new_v4());
+		let addr = "10.0.0.1".parse::<IpAddr>().unwrap();
+		let neighbour = "10.0.0.2".parse::<IpAddr>().unwrap();
+
+		let limits = SessionLimits::new().with_global(3).with_per_user(1).with_per_anon_bucket(1).with_anon_prefixes(24, 64);
+		let mut registry = SessionRegistry::with_limits(limits);
+		registry.connect::<()>(alice).unwrap();
+		assert_eq!(registry.connect::<()>(alice).unwrap_err(), SessionError::MaximumSessionsReached);
+		registry.connect_anon::<()>(addr).unwrap();
+		assert_eq!(registry.connect_anon::<()>(neighbour).unwrap_err(), SessionError::MaximumSessionsReached);
+		registry.connect::<()>(bob).unwrap();
+		assert_eq!(registry.connect::<()>(UserId::new_v4()).unwrap_err(), SessionError::MaximumSessionsReached);
+
+		let mut registry = SessionRegistry::with_limits(limits.on_limit(LimitAction::EvictOldest));
+		let old = registry.connect::<()>(alice).unwrap();
+		let new = registry.connect::<()>(alice).unwrap();
+		assert!(new.first_connected.is_none());
+		assert_eq!(new.evicted.len(), 1);
+		assert_eq!(new.evicted[0].disconnected, Disconnected::new(alice, old.connected.session_id));
+		assert!(new.evicted[0].last_disconnected.is_none());
+
+		let anon = registry.connect_anon::<()>(addr).unwrap();
+		registry.connect::<()>(bob).unwrap();
+		let evicting = registry.connect::<()>(UserId::new_v4()).unwrap();
+		assert_eq!(evicting.evicted.len(), 1);
+		assert_eq!(evicting.evicted[0].last_disconnected, Some(LastDisconnected::new(alice, new.connected.session_id)));
+		assert!(registry.contains(ANON_USER_ID, anon.connected.session_id));
+		assert_eq!(registry.len(), 3);
+
+		let oversized = SessionLimits { anon_ipv4_prefix: 40, anon_ipv6_prefix: 200, ..limits };
+		assert_eq!(oversized.bucket(addr), addr);
+		assert_eq!(oversized.bucket("::1".parse().unwrap()), "::1".parse::<IpAddr>().unwrap());
+	}
+}
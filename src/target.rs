@@ -8,6 +8,8 @@ pub type UserId = Uuid;
 pub type SessionId = u32;
 /// The bot ID type.
 pub type BotId = Uuid;
+/// The group ID type.
+pub type GroupId = Uuid;
 /// The correlation ID type.
 pub type CorrelationId = Uuid;
 /// The user ID of an anonymous target.
@@ -152,6 +154,34 @@ impl Target {
 		}
 	}
 
+	/// Checks whether every session (or bot) of the other target is also targeted by this target.
+	///
+	/// An [`AuthTarget::All`] contains every [`AuthTarget::Specific`] of the same user.
+	pub fn contains(&self, other: &Target) -> bool {
+		match (self, other) {
+			(Self::Auth(AuthTarget::All(a)), Self::Auth(b)) => *a == b.id(),
+			_ => self == other,
+		}
+	}
+
+	/// Returns everyone targeted by either of the targets.
+	pub fn union(&self, other: &Targets) -> Targets {
+		let targets: Targets = (*self).into();
+		targets.union(other)
+	}
+
+	/// Returns everyone targeted by both of the targets.
+	pub fn intersection(&self, other: &Targets) -> Targets {
+		let targets: Targets = (*self).into();
+		targets.intersection(other)
+	}
+
+	/// Returns everyone targeted by this target but not by the other targets.
+	pub fn difference(&self, other: &Targets) -> Targets {
+		let targets: Targets = (*self).into();
+		targets.difference(other)
+	}
+
 	/// Returns the target's user ID if it has one.
 	pub fn user_id(&self) -> Option<UserId> {
 		match self {
@@ -204,6 +234,52 @@ impl std::fmt::Display for Target {
 	}
 }
 
+impl std::str::FromStr for Target {
+	type Err = crate::ParseTargetError;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		use crate::ParseTargetError;
+
+		let parse_id = |id: &str| id.parse::<Uuid>().map_err(|_| ParseTargetError::InvalidId(id.to_string()));
+		let parse_session_id = |id: &str| id.parse::<SessionId>().map_err(|_| ParseTargetError::InvalidSessionId(id.to_string()));
+		let segments = s.split('/').collect::<Vec<_>>();
+		match segments[..] {
+			["anon", session_id] => Ok(Self::new_anon(parse_session_id(session_id)?)),
+			["auth", user_id] => Ok(Self::new_auth(parse_id(user_id)?)),
+			["auth", user_id, session_id] => Ok(Self::new_auth_specific(parse_id(user_id)?, parse_session_id(session_id)?)),
+			["bot", bot_id] => Ok(Self::new_bot(parse_id(bot_id)?)),
+			["anon" | "auth" | "bot", ..] => Err(ParseTargetError::Malformed(s.to_string())),
+			[kind, ..] => Err(ParseTargetError::UnknownKind(kind.to_string())),
+			[] => unreachable!("split always yields a segment"),
+		}
+	}
+}
+
+/// Serde support for serializing a [`Target`] as its compact [`Display`](std::fmt::Display) format,
+/// e.g. `auth/{uuid}/{sid}`.
+///
+/// Use as `#[serde(with = "wire::target::as_string")]` on a [`Target`] field.
+pub mod as_string {
+	use super::Target;
+
+	/// Serializes the target as a string.
+	pub fn serialize<S>(target: &Target, serializer: S) -> Result<S::Ok, S::Error>
+	where
+		S: serde::Serializer,
+	{
+		serializer.collect_str(target)
+	}
+
+	/// Deserializes the target from a string.
+	pub fn deserialize<'de, D>(deserializer: D) -> Result<Target, D::Error>
+	where
+		D: serde::Deserializer<'de>,
+	{
+		let s = <String as serde::Deserialize>::deserialize(deserializer)?;
+		s.parse().map_err(serde::de::Error::custom)
+	}
+}
+
 /// The targets that a message can be sent to.
 #[derive(Debug, serde::Serialize, serde::Deserialize, Clone, PartialEq, Eq)]
 pub enum Targets {
@@ -211,6 +287,247 @@ pub enum Targets {
 	All,
 	/// Targets only a set of specific targets.
 	Few(Vec<Target>),
+	/// Targets all members of a group.
+	Group(GroupId),
+	/// Targets everyone targeted by the inner targets except the excluded targets.
+	///
+	/// Excluding [`AuthTarget::All`] excludes every session of the user.
+	Except(Box<Targets>, Box<Targets>),
+	/// Targets everyone targeted by any of the targets.
+	Union(Vec<Targets>),
+	/// Targets everyone targeted by all of the targets.
+	///
+	/// An empty intersection targets no one.
+	Intersection(Vec<Targets>),
+}
+
+impl Targets {
+	/// Targets no one.
+	pub fn none() -> Self {
+		Self::Few(Vec::new())
+	}
+
+	/// Excludes the given targets from the targets.
+	///
+	/// Useful for broadcasting to everyone but the sender, e.g. `Targets::Group(room).except(from)`.
+	pub fn except(self, excluded: impl Into<Targets>) -> Self {
+		Self::Except(Box::new(self), Box::new(excluded.into()))
+	}
+
+	/// Checks whether every session (or bot) of the target is targeted.
+	///
+	/// [`Targets::All`] does not contain bots. Group membership is not known here, so a group contains nothing,
+	/// use the [`Resolver`](crate::Resolver) to resolve groups.
+	pub fn contains(&self, target: &Target) -> bool {
+		match self {
+			Self::All => !target.is_bot(),
+			Self::Few(targets) => targets.iter().any(|t| t.contains(target)),
+			Self::Group(..) => false,
+			Self::Except(inner, excluded) => inner.contains(target) && !excluded.may_overlap(target),
+			Self::Union(targets) => targets.iter().any(|t| t.contains(target)),
+			Self::Intersection(targets) => !targets.is_empty() && targets.iter().all(|t| t.contains(target)),
+		}
+	}
+
+	/// Checks whether everyone targeted by the other targets is targeted as well.
+	///
+	/// The check is conservative: it may return `false` for supersets it cannot prove (e.g. involving groups).
+	pub fn is_superset(&self, other: &Targets) -> bool {
+		if self == other {
+			return true
+		}
+		if let Self::Union(targets) = self {
+			if targets.iter().any(|t| t.is_superset(other)) {
+				return true
+			}
+		}
+
+		match other {
+			Self::All | Self::Group(..) => false,
+			Self::Few(targets) => targets.iter().all(|t| self.contains(t)),
+			Self::Except(inner, _) => self.is_superset(inner),
+			Self::Union(targets) => targets.iter().all(|t| self.is_superset(t)),
+			Self::Intersection(targets) => targets.is_empty() || targets.iter().any(|t| self.is_superset(t)),
+		}
+	}
+
+	/// Returns everyone targeted by either of the targets.
+	pub fn union(&self, other: &Targets) -> Targets {
+		if self.is_superset(other) {
+			return self.normalized()
+		}
+		if other.is_superset(self) {
+			return other.normalized()
+		}
+
+		match (self, other) {
+			(Self::Few(a), Self::Few(b)) => {
+				let mut targets = Vec::with_capacity(a.len() + b.len());
+				for target in a.iter().chain(b) {
+					insert_target(&mut targets, *target);
+				}
+				Self::Few(targets)
+			},
+			_ => {
+				let mut targets = Vec::new();
+				for side in [self, other] {
+					match side {
+						Self::Union(inner) => targets.extend(inner.iter().cloned()),
+						_ => targets.push(side.clone()),
+					}
+				}
+				Self::Union(targets)
+			},
+		}
+	}
+
+	/// Returns everyone targeted by both of the targets.
+	pub fn intersection(&self, other: &Targets) -> Targets {
+		if self.is_superset(other) {
+			return other.normalized()
+		}
+		if other.is_superset(self) {
+			return self.normalized()
+		}
+
+		match (self, other) {
+			(Self::Few(a), Self::Few(b)) => {
+				let mut targets = Vec::new();
+				for (a, b) in a.iter().flat_map(|a| b.iter().map(move |b| (a, b))) {
+					if a.contains(b) {
+						insert_target(&mut targets, *b);
+					} else if b.contains(a) {
+						insert_target(&mut targets, *a);
+					}
+				}
+				Self::Few(targets)
+			},
+			(Self::All, Self::Few(few)) | (Self::Few(few), Self::All) => {
+				let mut targets = Vec::new();
+				for target in few.iter().filter(|target| !target.is_bot()) {
+					insert_target(&mut targets, *target);
+				}
+				Self::Few(targets)
+			},
+			_ => {
+				let mut targets = Vec::new();
+				for side in [self, other] {
+					match side {
+						Self::Intersection(inner) => targets.extend(inner.iter().cloned()),
+						_ => targets.push(side.clone()),
+					}
+				}
+				Self::Intersection(targets)
+			},
+		}
+	}
+
+	/// Returns everyone targeted by these targets but not by the other targets.
+	pub fn difference(&self, other: &Targets) -> Targets {
+		if other.is_superset(self) {
+			return Self::none()
+		}
+
+		match (self, other) {
+			(Self::Few(a), Self::Few(b)) => {
+				let mut kept = Vec::with_capacity(a.len());
+				for target in a.iter().filter(|target| !other.contains(target)) {
+					insert_target(&mut kept, *target);
+				}
+				let overlapping = b.iter().filter(|b| kept.iter().any(|a| a.contains(b))).copied().collect::<Vec<_>>();
+				if overlapping.is_empty() {
+					Self::Few(kept)
+				} else {
+					Self::Few(kept).except(overlapping)
+				}
+			},
+			(Self::Except(inner, excluded), _) => Self::Except(inner.clone(), Box::new(excluded.union(other))),
+			_ => self.clone().except(other.clone()),
+		}
+	}
+
+	/// Returns the targets with duplicate and redundant targets of [`Targets::Few`] removed.
+	fn normalized(&self) -> Targets {
+		match self {
+			Self::Few(targets) => {
+				let mut normalized = Vec::with_capacity(targets.len());
+				for target in targets {
+					insert_target(&mut normalized, *target);
+				}
+				Self::Few(normalized)
+			},
+			_ => self.clone(),
+		}
+	}
+
+	/// Checks whether the targets may target some session (or bot) of the target.
+	fn may_overlap(&self, target: &Target) -> bool {
+		match self {
+			Self::All => !target.is_bot(),
+			Self::Few(targets) => targets.iter().any(|t| t.contains(target) || target.contains(t)),
+			Self::Group(..) => true,
+			Self::Except(inner, _) => inner.may_overlap(target),
+			Self::Union(targets) => targets.iter().any(|t| t.may_overlap(target)),
+			Self::Intersection(targets) => !targets.is_empty() && targets.iter().all(|t| t.may_overlap(target)),
+		}
+	}
+}
+
+/// Inserts a target into a deduplicated list of targets, keeping only the broadest targets.
+fn insert_target(targets: &mut Vec<Target>, target: Target) {
+	if targets.iter().any(|t| t.contains(&target)) {
+		return
+	}
+	targets.retain(|t| !target.contains(t));
+	targets.push(target);
+}
+
+impl<T: Into<Targets>> std::ops::BitOr<T> for Targets {
+	type Output = Targets;
+
+	fn bitor(self, rhs: T) -> Self::Output {
+		self.union(&rhs.into())
+	}
+}
+
+impl<T: Into<Targets>> std::ops::BitAnd<T> for Targets {
+	type Output = Targets;
+
+	fn bitand(self, rhs: T) -> Self::Output {
+		self.intersection(&rhs.into())
+	}
+}
+
+impl<T: Into<Targets>> std::ops::Sub<T> for Targets {
+	type Output = Targets;
+
+	fn sub(self, rhs: T) -> Self::Output {
+		self.difference(&rhs.into())
+	}
+}
+
+impl<T: Into<Targets>> std::ops::BitOr<T> for Target {
+	type Output = Targets;
+
+	fn bitor(self, rhs: T) -> Self::Output {
+		self.union(&rhs.into())
+	}
+}
+
+impl<T: Into<Targets>> std::ops::BitAnd<T> for Target {
+	type Output = Targets;
+
+	fn bitand(self, rhs: T) -> Self::Output {
+		self.intersection(&rhs.into())
+	}
+}
+
+impl<T: Into<Targets>> std::ops::Sub<T> for Target {
+	type Output = Targets;
+
+	fn sub(self, rhs: T) -> Self::Output {
+		self.difference(&rhs.into())
+	}
 }
 
 /// An endless (u32-endless) pool of `Target`s.
@@ -249,3 +566,66 @@ impl Default for UserPool {
 		Self { curr: 1 } // 0 is reserved for anon users
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::ParseTargetError;
+
+	#[test]
+	fn test_targets_algebra() {
+		let mut pool = UserPool::default();
+		let (alice, bob, bot) = (pool.next_auth(), pool.next_anon(), pool.next_bot());
+		let everyone = alice.for_all();
+		let group = Targets::Group(GroupId::new_v4());
+
+		assert!(everyone.contains(&alice));
+		assert!(!alice.contains(&everyone));
+		assert_eq!(alice | everyone, Targets::Few(vec![everyone]));
+		assert_eq!(alice | bob | alice, Targets::Few(vec![alice, bob]));
+		assert_eq!(everyone & alice, Targets::Few(vec![alice]));
+		assert_eq!(alice & bob, Targets::none());
+		assert_eq!(alice - everyone, Targets::none());
+		assert_eq!(everyone - alice, Targets::Few(vec![everyone]).except(alice));
+		assert_eq!((alice | bob) - bob, Targets::Few(vec![alice]));
+		assert_eq!(Targets::Few(vec![alice, alice]) | Targets::none(), Targets::Few(vec![alice]));
+		assert_eq!(Targets::Few(vec![alice, alice]) | bob, Targets::Few(vec![alice, bob]));
+		assert_eq!(Targets::Few(vec![alice, alice, bob]) - bob, Targets::Few(vec![alice]));
+		let carol = pool.next_auth();
+		assert_eq!(Targets::Few(vec![alice, everyone]) | carol, Targets::Few(vec![everyone, carol]));
+		assert_eq!(Targets::Few(vec![alice, everyone, alice]) & Targets::Few(vec![everyone]), Targets::Few(vec![everyone]));
+
+		assert_eq!(Targets::All | alice, Targets::All);
+		assert_eq!(Targets::All | bot, Targets::Union(vec![Targets::All, bot.into()]));
+		assert_eq!(Targets::All & (alice | bot), Targets::Few(vec![alice]));
+		assert_eq!((Targets::All - alice) - bob, Targets::All.except(alice | bob));
+		assert_eq!(bob - Targets::All, Targets::none());
+		assert_eq!(group.clone() | group.clone(), group);
+
+		let others = Targets::All - everyone;
+		assert!(others.contains(&bob));
+		assert!(!others.contains(&alice));
+		assert!(!others.contains(&bot));
+		assert!(!(group.clone() - bob).contains(&alice));
+		assert!((group.clone() | alice).contains(&alice));
+		assert!(Targets::All.is_superset(&(alice | bob)));
+		assert!(!Targets::All.is_superset(&group));
+	}
+
+	#[test]
+	fn test_target_from_str() {
+		let mut pool = UserPool::default();
+		for target in [pool.next_anon(), pool.next_auth(), pool.next_auth().for_all(), pool.next_bot()] {
+			assert_eq!(target.to_string().parse::<Target>(), Ok(target));
+		}
+
+		let user_id = Uuid::new_v4();
+		assert_eq!(format!("auth/{user_id}/x").parse::<Target>(), Err(ParseTargetError::InvalidSessionId("x".to_string())));
+		assert_eq!("bot/x".parse::<Target>(), Err(ParseTargetError::InvalidId("x".to_string())));
+		assert_eq!("anon".parse::<Target>(), Err(ParseTargetError::Malformed("anon".to_string())));
+		assert_eq!("user/1".parse::<Target>(), Err(ParseTargetError::UnknownKind("user".to_string())));
+
+		let de = serde::de::IntoDeserializer::<serde::de::value::Error>::into_deserializer("anon/7");
+		assert_eq!(as_string::deserialize(de), Ok(Target::new_anon(7)));
+	}
+}
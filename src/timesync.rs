@@ -0,0 +1,232 @@
+//! Common utilities for estimating the offset between the client and server clocks.
+//!
+//! The client periodically sends a [`TimePing`], which the server answers with a [`TimePong`] carrying
+//! its receive and send times. From the four timestamps of an exchange the client's [`TimeSync`] estimates
+//! the clock offset NTP-style, smoothing it over multiple exchanges.
+
+use std::collections::VecDeque;
+
+use crate::*;
+
+/// A time-sync probe sent by the client.
+#[derive(Debug, serde::Serialize, serde::Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TimePing {
+	/// The nonce identifying the exchange.
+	pub nonce: u64,
+	/// The client time when the probe was sent (in ms).
+	pub client_sent: i64,
+}
+
+/// A reply to a [`TimePing`] sent by the server.
+#[derive(Debug, serde::Serialize, serde::Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TimePong {
+	/// The nonce of the probe.
+	pub nonce: u64,
+	/// The client time when the probe was sent (in ms).
+	pub client_sent: i64,
+	/// The server time when the probe was received (in ms).
+	pub server_received: i64,
+	/// The server time when the reply was sent (in ms).
+	pub server_sent: i64,
+}
+
+impl TimePong {
+	/// Creates a reply to the probe received and answered at the given server times (in ms).
+	pub fn new(ping: &TimePing, server_received: i64, server_sent: i64) -> Self {
+		Self {
+			nonce: ping.nonce,
+			client_sent: ping.client_sent,
+			server_received,
+			server_sent,
+		}
+	}
+
+	/// Creates a reply to the probe, received and answered now.
+	pub fn reply(ping: &TimePing, clock: impl Clock) -> Self {
+		let now = clock.now_millis();
+		Self::new(ping, now, now)
+	}
+}
+
+/// A single clock-offset measurement.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TimeSample {
+	/// The server clock minus the client clock (in ms).
+	pub offset: i64,
+	/// The round-trip time, excluding the server processing time (in ms).
+	pub rtt: i64,
+}
+
+/// A client-side estimator of the server clock offset.
+///
+/// Every exchange yields a [`TimeSample`] which is smoothed into the estimate with an exponentially
+/// weighted moving average. Samples with a much larger round-trip time than the best one seen are
+/// less accurate and therefore ignored, see [`TimeSync::MAX_RTT_FACTOR`].
+///
+/// Every probe is answered at most once: replies to unknown, already answered or forgotten probes are ignored.
+#[derive(Debug, Clone)]
+pub struct TimeSync {
+	/// The weight of a new sample in the moving average.
+	smoothing: f64,
+	/// The nonce of the next probe.
+	next_nonce: u64,
+	/// The nonces of the probes awaiting a reply, oldest first.
+	outstanding: VecDeque<u64>,
+	/// The smoothed offset (in ms).
+	offset: Option<f64>,
+	/// The smoothed round-trip time (in ms).
+	rtt: Option<f64>,
+	/// The lowest round-trip time seen (in ms).
+	min_rtt: Option<i64>,
+}
+
+impl TimeSync {
+	/// The default weight of a new sample.
+	pub const DEFAULT_SMOOTHING: f64 = 0.2;
+	/// How many times the best round-trip time a sample's round-trip time may be before it is ignored.
+	pub const MAX_RTT_FACTOR: i64 = 2;
+	/// The slack added to the maximum round-trip time, so that jitter on fast links does not discard every sample (in ms).
+	pub const RTT_SLACK: i64 = 10;
+	/// The maximum number of probes awaiting a reply, older ones are forgotten.
+	pub const MAX_OUTSTANDING: usize = 16;
+
+	/// Creates a new estimator with the default smoothing.
+	pub fn new() -> Self {
+		Self::with_smoothing(Self::DEFAULT_SMOOTHING)
+	}
+
+	/// Creates a new estimator weighting new samples by `smoothing` (clamped to `(0, 1]`).
+	pub fn with_smoothing(smoothing: f64) -> Self {
+		Self {
+			smoothing: smoothing.clamp(f64::EPSILON, 1.0),
+			next_nonce: 0,
+			outstanding: VecDeque::new(),
+			offset: None,
+			rtt: None,
+			min_rtt: None,
+		}
+	}
+
+	/// Creates the next probe to send to the server.
+	pub fn ping(&mut self, clock: impl Clock) -> TimePing {
+		let nonce = self.next_nonce;
+		self.next_nonce += 1;
+		if self.outstanding.len() == Self::MAX_OUTSTANDING {
+			self.outstanding.pop_front();
+		}
+		self.outstanding.push_back(nonce);
+		TimePing {
+			nonce,
+			client_sent: clock.now_millis(),
+		}
+	}
+
+	/// Incorporates a reply from the server, received now.
+	///
+	/// Returns the measured sample, or [`None`] if the reply was not for an outstanding probe or was ignored.
+	pub fn pong(&mut self, pong: &TimePong, clock: impl Clock) -> Option<TimeSample> {
+		self.pong_at(pong, clock.now_millis())
+	}
+
+	/// Incorporates a reply from the server, received at the given client time (in ms).
+	pub fn pong_at(&mut self, pong: &TimePong, client_received: i64) -> Option<TimeSample> {
+		let index = self.outstanding.iter().position(|nonce| *nonce == pong.nonce)?;
+		self.outstanding.remove(index);
+
+		// the timestamps come from the wire, so a malformed reply must not overflow
+		let (client_sent, client_received) = (pong.client_sent as i128, client_received as i128);
+		let (server_received, server_sent) = (pong.server_received as i128, pong.server_sent as i128);
+		let offset = i64::try_from(((server_received - client_sent) + (server_sent - client_received)) / 2).ok()?;
+		let rtt = i64::try_from(((client_received - client_sent) - (server_sent - server_received)).max(0)).ok()?;
+		let sample = TimeSample { offset, rtt };
+
+		let min_rtt = self.min_rtt.map_or(rtt, |min_rtt| min_rtt.min(rtt));
+		self.min_rtt = Some(min_rtt);
+		if rtt > Self::MAX_RTT_FACTOR.saturating_mul(min_rtt).saturating_add(Self::RTT_SLACK) {
+			return None
+		}
+
+		let smooth = |estimate: Option<f64>, value: i64| Some(estimate.map_or(value as f64, |estimate| estimate + self.smoothing * (value as f64 - estimate)));
+		self.offset = smooth(self.offset, offset);
+		self.rtt = smooth(self.rtt, rtt);
+		Some(sample)
+	}
+
+	/// Returns the estimated server clock minus the client clock (in ms).
+	pub fn offset(&self) -> Option<i64> {
+		self.offset.map(|offset| offset.round() as i64)
+	}
+
+	/// Returns the estimated round-trip time (in ms).
+	pub fn rtt(&self) -> Option<i64> {
+		self.rtt.map(|rtt| rtt.round() as i64)
+	}
+
+	/// Checks whether any sample was incorporated yet.
+	pub fn is_synced(&self) -> bool {
+		self.offset.is_some()
+	}
+
+	/// Converts a server timestamp (e.g. [`TimestampedEvent::timestamp`]) into the client timeline.
+	///
+	/// Returns the timestamp unchanged until synced.
+	pub fn server_to_local(&self, timestamp: i64) -> i64 {
+		timestamp - self.offset().unwrap_or(0)
+	}
+
+	/// Converts a client timestamp into the server timeline.
+	///
+	/// Returns the timestamp unchanged until synced.
+	pub fn local_to_server(&self, timestamp: i64) -> i64 {
+		timestamp + self.offset().unwrap_or(0)
+	}
+}
+
+impl Default for TimeSync {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_time_sync() {
+		let mut sync = TimeSync::new();
+		assert_eq!(sync.server_to_local(5_000), 5_000);
+
+		// the server clock is 5s ahead, the one-way latency is 50ms and the server takes 10ms to reply
+		let (client, server) = (FixedClock(1_000), 6_000);
+		let ping = sync.ping(client);
+		let pong = TimePong::new(&ping, server + 50, server + 60);
+		let sample = sync.pong_at(&pong, 1_110).unwrap();
+		assert_eq!(sample, TimeSample { offset: 5_000, rtt: 100 });
+		assert_eq!(sync.offset(), Some(5_000));
+		assert_eq!(sync.server_to_local(7_000), 2_000);
+		assert_eq!(sync.local_to_server(2_000), 7_000);
+
+		// asymmetric latency skews a single sample, which is smoothed
+		let ping = sync.ping(FixedClock(2_000));
+		let pong = TimePong::new(&ping, 7_100, 7_100);
+		assert_eq!(sync.pong_at(&pong, 2_120).unwrap().offset, 5_040);
+		assert_eq!(sync.offset(), Some(5_008));
+
+		// congested exchanges, unknown nonces and replayed replies are ignored
+		let ping = sync.ping(FixedClock(3_000));
+		assert_eq!(sync.pong_at(&TimePong::reply(&ping, FixedClock(8_000)), 4_000), None);
+		assert_eq!(sync.pong_at(&TimePong { nonce: 99, ..pong }, 2_120), None);
+		assert_eq!(sync.pong_at(&pong, 2_120), None);
+		let ping = TimePing { client_sent: i64::MIN, ..sync.ping(FixedClock(3_000)) };
+		assert_eq!(sync.pong_at(&TimePong::new(&ping, i64::MAX, i64::MAX), i64::MAX), None);
+		assert_eq!(sync.offset(), Some(5_008));
+		assert_eq!(sync.rtt(), Some(104));
+
+		// only the most recent probes are awaited
+		let first = sync.ping(FixedClock(4_000));
+		let last = (0..TimeSync::MAX_OUTSTANDING).map(|_| sync.ping(FixedClock(4_000))).last().unwrap();
+		assert_eq!(sync.pong_at(&TimePong::new(&first, 9_050, 9_050), 4_100), None);
+		assert!(sync.pong_at(&TimePong::new(&last, 9_050, 9_050), 4_100).is_some());
+	}
+}
@@ -1,10 +1,44 @@
+use std::collections::HashSet;
+
 use proc_macro::TokenStream;
 use proc_macro2::Span;
-use syn::{parse_macro_input, spanned::Spanned, token::Pub, Data, DeriveInput, Error};
+use syn::{parse_macro_input, token::Pub, visit::Visit, Data, DeriveInput, Error};
 use quote::*;
 
 /// Derives a struct for each enum variant, with the same name as the variant.
 ///
+/// Also derives `From<Variant> for Enum` and `TryFrom<Enum> for Variant` conversions, so the generated structs
+/// can be passed anywhere an `impl Into<Enum>` is expected (e.g. `Req::new`, `Res::new` and `Error::new`).
+///
+/// Also derives a fieldless `EnumKind` discriminant enum with `Display`, `FromStr` and serde support,
+/// exposed through `Enum::kind` and `Variant::KIND`.
+///
+/// # Bevy
+/// With the `bevy` feature enabled, enums marked with `#[wire(bevy)]` also get every generated struct implementing
+/// bevy's `Event` and the enum implementing `wire::bevy::WireEvents` for use with `wire::bevy::WirePlugin`.
+/// Events must be `Send + Sync + 'static`, so the integration is opt-in per enum.
+///
+/// # Generics
+/// Type, lifetime and const parameters are supported. Each generated struct only receives
+/// the parameters (and where-clause predicates) its fields actually use.
+///
+/// # Attributes
+/// The generation can be customized with `#[wire(...)]` helper attributes:
+/// - on the enum:
+///   - `module = "name"` - generates the structs inside a nested module with the enum's visibility, so that multiple
+///     enums sharing variant names can live in the same scope (must be used on enums declared at module level)
+///   - `reexport` - also re-exports the generated structs from the module into the enclosing scope
+///   - `exclude(path, ...)` - does not copy enum attributes with the given paths (e.g. `serde`) onto the structs
+///   - `derive(Trait, ...)` - adds extra derives onto all structs
+///   - `attr(meta, ...)` - adds extra attributes onto all structs
+///   - `bevy` - implements the `bevy` integration (ignored without the `bevy` feature)
+/// - on a variant:
+///   - `skip` - does not generate a struct (nor conversions) for the variant
+///   - `rename = "Name"` - names the generated struct differently from the variant
+///   - `exclude(..)`, `derive(..)`, `attr(..)` - same as above, but only for this variant
+/// - on a field:
+///   - `attr(meta, ...)` - adds extra attributes onto the generated struct's field only
+///
 /// # Example
 /// ```
 /// #[derive(wire::WireObj)]
@@ -15,7 +49,7 @@ use quote::*;
 /// 	Bar(u32, u32),
 /// }
 /// ```
-#[proc_macro_derive(WireObj)]
+#[proc_macro_derive(WireObj, attributes(wire))]
 pub fn derive_wire_obj(input: TokenStream) -> TokenStream {
 	let mut input = parse_macro_input!(input as DeriveInput);
 
@@ -24,63 +58,130 @@ pub fn derive_wire_obj(input: TokenStream) -> TokenStream {
 		_ => return Error::new(input.ident.span(), "wire only works on enums").into_compile_error().into(),
 	};
 
-	if input.generics.lt_token.is_some() {
-		return Error::new(input.generics.span(), "wire does not support generics").into_compile_error().into()
-	}
-
-	let variant_structs = data
+	let options = match Options::parse(&input.attrs, &["module", "reexport", "exclude", "derive", "attr", "bevy"]) {
+		Ok(options) => options,
+		Err(err) => return err.into_compile_error().into(),
+	};
+	let variants = match data
 		.variants
 		.iter()
-		.map(|v| {
-			let name = v.ident.clone();
+		.map(|v| Options::parse(&v.attrs, &["skip", "rename", "exclude", "derive", "attr"]).map(|options| (v, options)))
+		.collect::<syn::Result<Vec<_>>>()
+	{
+		Ok(variants) => variants,
+		Err(err) => return err.into_compile_error().into(),
+	};
+
+	let ident = &input.ident;
+	let variant_structs = variants
+		.iter()
+		.filter(|(_, v_options)| !v_options.skip)
+		.map(|(v, v_options)| {
+			let name = v_options.struct_name(v);
+			let generics = variant_generics(&input.generics, &v.fields);
+			let where_clause = &generics.where_clause;
+			let attrs = input
+				.attrs
+				.iter()
+				.filter(|attr| !attr.path.is_ident("wire") && !options.excludes(attr) && !v_options.excludes(attr))
+				.cloned()
+				.chain(options.extra_attrs())
+				.chain(v_options.extra_attrs())
+				.collect::<Vec<_>>();
+			let fields = match v
+				.fields
+				.iter()
+				.map(|field| {
+					let field_options = Options::parse(&field.attrs, &["attr"])?;
+					Ok(syn::Field {
+						vis: syn::Visibility::Public(syn::VisPublic { pub_token: Pub(Span::call_site()) }),
+						attrs: field.attrs.iter().filter(|attr| !attr.path.is_ident("wire")).cloned().chain(field_options.extra_attrs()).collect(),
+						..field.clone()
+					})
+				})
+				.collect::<syn::Result<Vec<_>>>()
+			{
+				Ok(fields) => fields,
+				Err(err) => return err.into_compile_error(),
+			};
 			match &v.fields {
 				syn::Fields::Named(..) => {
-					let fields = v
-						.fields
-						.iter()
-						.cloned()
-						.map(|field| syn::Field {
-							vis: syn::Visibility::Public(syn::VisPublic { pub_token: Pub(Span::call_site()) }),
-							..field
-						})
-						.collect::<Vec<_>>();
-					let attrs = &input.attrs;
 					quote! {
 						#(#attrs)*
-						pub struct #name {
+						pub struct #name #generics #where_clause {
 							#(#fields),*
 						}
 					}
 				},
 				syn::Fields::Unnamed(..) => {
-					let fields = v
-						.fields
-						.iter()
-						.cloned()
-						.map(|field| syn::Field {
-							vis: syn::Visibility::Public(syn::VisPublic { pub_token: Pub(Span::call_site()) }),
-							..field
-						})
-						.collect::<Vec<_>>();
-					let attrs = &input.attrs;
 					quote! {
 						#(#attrs)*
-						pub struct #name (#(#fields),*);
+						pub struct #name #generics (#(#fields),*) #where_clause;
 					}
 				},
 				syn::Fields::Unit => {
-					let attrs = &input.attrs;
 					quote! {
 						#(#attrs)*
-						pub struct #name;
+						pub struct #name #generics #where_clause;
 					}
 				},
 			}
 		})
 		.collect::<Vec<_>>();
 
-	let res = quote! {
+	let scope = match options.module {
+		Some(..) => quote! { super:: },
+		None => quote! {},
+	};
+	let conversions = variants
+		.iter()
+		.filter(|(_, v_options)| !v_options.skip)
+		.map(|(v, v_options)| variant_conversions(ident, &input.generics, v, &v_options.struct_name(v), &scope))
+		.collect::<Vec<_>>();
+	let kind = kind_enum(ident, &input.generics, &variants);
+	#[cfg(feature = "bevy")]
+	let (variant_events, wire_events) = match options.bevy {
+		true => (
+			variants
+				.iter()
+				.filter(|(_, v_options)| !v_options.skip)
+				.map(|(v, v_options)| bevy::variant_event(&input.generics, v, &v_options.struct_name(v)))
+				.collect::<Vec<_>>(),
+			bevy::wire_events(ident, &input.generics, &variants, options.module.as_ref()),
+		),
+		false => (Vec::new(), quote! {}),
+	};
+	#[cfg(not(feature = "bevy"))]
+	let (variant_events, wire_events) = (Vec::<proc_macro2::TokenStream>::new(), quote! {});
+
+	let generated = quote! {
 		#(#variant_structs)*
+		#(#conversions)*
+		#(#variant_events)*
+	};
+	let res = match &options.module {
+		Some(module) => {
+			let vis = &input.vis;
+			let doc = format!("Structs generated from the variants of [`{}`].", ident);
+			let reexport = options.reexport.then(|| quote! { #vis use #module::*; });
+			quote! {
+				#[doc = #doc]
+				#vis mod #module {
+					#[allow(unused_imports)]
+					use super::*;
+
+					#generated
+				}
+				#reexport
+				#kind
+				#wire_events
+			}
+		},
+		None => quote! {
+			#generated
+			#kind
+			#wire_events
+		},
 	};
 
 	// use std::io::Write;
@@ -95,6 +196,385 @@ pub fn derive_wire_obj(input: TokenStream) -> TokenStream {
 	res.into()
 }
 
+/// Options of the `#[wire(...)]` helper attributes on an enum, a variant or a field.
+#[derive(Default)]
+struct Options {
+	/// Skips generating a struct for the variant.
+	skip: bool,
+	/// Renames the struct generated for the variant.
+	rename: Option<syn::Ident>,
+	/// The module the structs are generated in.
+	module: Option<syn::Ident>,
+	/// Re-exports the generated structs from the module into the enclosing scope.
+	reexport: bool,
+	/// Implements the `bevy` integration.
+	bevy: bool,
+	/// The paths of enum attributes which are not copied onto the generated structs.
+	exclude: Vec<syn::Path>,
+	/// The extra attributes added onto the generated structs (or fields).
+	attrs: Vec<syn::Meta>,
+}
+
+impl Options {
+	/// Parses the options from all `#[wire(...)]` attributes, accepting only the `allowed` ones.
+	fn parse(attrs: &[syn::Attribute], allowed: &[&str]) -> syn::Result<Self> {
+		let mut options = Self::default();
+		for attr in attrs.iter().filter(|attr| attr.path.is_ident("wire")) {
+			let syn::Meta::List(list) = attr.parse_meta()? else {
+				return Err(Error::new_spanned(attr, "expected `#[wire(...)]`"))
+			};
+
+			for nested in list.nested {
+				let syn::NestedMeta::Meta(meta) = nested else {
+					return Err(Error::new_spanned(nested, "expected a wire option"))
+				};
+				let key = meta.path().get_ident().map(ToString::to_string).unwrap_or_default();
+				if !allowed.contains(&key.as_str()) {
+					return Err(Error::new_spanned(meta.path(), format!("unsupported wire option, expected one of: {}", allowed.join(", "))))
+				}
+
+				match meta {
+					syn::Meta::Path(..) if key == "skip" => options.skip = true,
+					syn::Meta::Path(..) if key == "reexport" => options.reexport = true,
+					syn::Meta::Path(..) if key == "bevy" => options.bevy = true,
+					syn::Meta::NameValue(syn::MetaNameValue { lit: syn::Lit::Str(lit), .. }) if key == "module" => options.module = Some(lit.parse()?),
+					syn::Meta::NameValue(syn::MetaNameValue { lit: syn::Lit::Str(lit), .. }) if key == "rename" => options.rename = Some(lit.parse()?),
+					syn::Meta::List(list) if key == "exclude" => {
+						for nested in list.nested {
+							let syn::NestedMeta::Meta(syn::Meta::Path(path)) = nested else {
+								return Err(Error::new_spanned(nested, "expected an attribute path"))
+							};
+							options.exclude.push(path);
+						}
+					},
+					syn::Meta::List(..) if key == "derive" => options.attrs.push(meta),
+					syn::Meta::List(list) if key == "attr" => {
+						for nested in list.nested {
+							let syn::NestedMeta::Meta(meta) = nested else {
+								return Err(Error::new_spanned(nested, "expected an attribute"))
+							};
+							options.attrs.push(meta);
+						}
+					},
+					meta => return Err(Error::new_spanned(meta, format!("malformed wire option `{}`", key))),
+				}
+			}
+		}
+
+		Ok(options)
+	}
+
+	/// Returns the name of the struct generated for the variant.
+	fn struct_name(&self, v: &syn::Variant) -> syn::Ident {
+		self.rename.clone().unwrap_or_else(|| v.ident.clone())
+	}
+
+	/// Checks whether the attribute is excluded from being copied.
+	fn excludes(&self, attr: &syn::Attribute) -> bool {
+		self.exclude.contains(&attr.path)
+	}
+
+	/// Returns the extra attributes.
+	fn extra_attrs(&self) -> impl Iterator<Item = syn::Attribute> + '_ {
+		self.attrs.iter().map(|meta| syn::parse_quote!(#[#meta]))
+	}
+}
+
+/// Collects the generic parameters mentioned in a syntax tree.
+#[derive(Default)]
+struct UsedParams {
+	/// The names of all generic parameters of the enum.
+	params: HashSet<String>,
+	/// The names of the generic parameters that were encountered.
+	used: HashSet<String>,
+}
+
+impl UsedParams {
+	/// Creates a collector for the parameters of the given generics.
+	fn new(generics: &syn::Generics) -> Self {
+		let params = generics.params.iter().map(param_name).collect();
+		Self { params, used: HashSet::new() }
+	}
+
+	/// Returns the names of the parameters used by a syntax tree.
+	fn of<T>(generics: &syn::Generics, visit: impl FnOnce(&mut Self, &T), node: &T) -> HashSet<String> {
+		let mut this = Self::new(generics);
+		visit(&mut this, node);
+		this.used
+	}
+}
+
+impl<'ast> Visit<'ast> for UsedParams {
+	fn visit_lifetime(&mut self, lifetime: &'ast syn::Lifetime) {
+		let name = lifetime.to_string();
+		if self.params.contains(&name) {
+			self.used.insert(name);
+		}
+	}
+
+	fn visit_path(&mut self, path: &'ast syn::Path) {
+		if path.leading_colon.is_none() {
+			if let Some(segment) = path.segments.first() {
+				let name = segment.ident.to_string();
+				if self.params.contains(&name) {
+					self.used.insert(name);
+				}
+			}
+		}
+		syn::visit::visit_path(self, path);
+	}
+}
+
+/// Returns the name of a generic parameter (lifetimes including the leading `'`).
+fn param_name(param: &syn::GenericParam) -> String {
+	match param {
+		syn::GenericParam::Type(param) => param.ident.to_string(),
+		syn::GenericParam::Lifetime(param) => param.lifetime.to_string(),
+		syn::GenericParam::Const(param) => param.ident.to_string(),
+	}
+}
+
+/// Derives the generics of a variant struct from the generics of the enum.
+///
+/// Keeps only the parameters used by the fields and drops every bound or where-clause predicate
+/// that mentions a parameter that was not kept.
+fn variant_generics(generics: &syn::Generics, fields: &syn::Fields) -> syn::Generics {
+	let used = UsedParams::of(generics, |v, fields| v.visit_fields(fields), fields);
+	let keeps = |used_by: HashSet<String>| used_by.is_subset(&used);
+
+	let params = generics
+		.params
+		.iter()
+		.filter(|param| used.contains(&param_name(param)))
+		.cloned()
+		.map(|mut param| {
+			match &mut param {
+				syn::GenericParam::Type(param) => {
+					param.bounds = param.bounds.iter().filter(|bound| keeps(UsedParams::of(generics, |v, b| v.visit_type_param_bound(b), *bound))).cloned().collect();
+				},
+				syn::GenericParam::Lifetime(param) => {
+					param.bounds = param.bounds.iter().filter(|bound| used.contains(&bound.to_string())).cloned().collect();
+				},
+				syn::GenericParam::Const(..) => {},
+			}
+			param
+		})
+		.collect::<syn::punctuated::Punctuated<_, syn::Token![,]>>();
+
+	let where_clause = generics.where_clause.as_ref().map(|where_clause| syn::WhereClause {
+		where_token: where_clause.where_token,
+		predicates: where_clause
+			.predicates
+			.iter()
+			.filter_map(|predicate| match predicate {
+				syn::WherePredicate::Type(predicate) => {
+					if !keeps(UsedParams::of(generics, |v, t| v.visit_type(t), &predicate.bounded_ty)) {
+						return None
+					}
+					let mut predicate = predicate.clone();
+					predicate.bounds = predicate.bounds.iter().filter(|bound| keeps(UsedParams::of(generics, |v, b| v.visit_type_param_bound(b), *bound))).cloned().collect();
+					(!predicate.bounds.is_empty()).then_some(syn::WherePredicate::Type(predicate))
+				},
+				syn::WherePredicate::Lifetime(predicate) => {
+					if !used.contains(&predicate.lifetime.to_string()) {
+						return None
+					}
+					let mut predicate = predicate.clone();
+					predicate.bounds = predicate.bounds.iter().filter(|bound| used.contains(&bound.to_string())).cloned().collect();
+					(!predicate.bounds.is_empty()).then_some(syn::WherePredicate::Lifetime(predicate))
+				},
+				syn::WherePredicate::Eq(..) => Some(predicate.clone()),
+			})
+			.collect(),
+	});
+
+	syn::Generics {
+		lt_token: (!params.is_empty()).then(Default::default),
+		gt_token: (!params.is_empty()).then(Default::default),
+		params,
+		where_clause: where_clause.filter(|where_clause| !where_clause.predicates.is_empty()),
+	}
+}
+
+/// Generates the fieldless `EnumKind` discriminant enum mirroring the variants of the enum.
+fn kind_enum(ident: &syn::Ident, generics: &syn::Generics, variants: &[(&syn::Variant, Options)]) -> proc_macro2::TokenStream {
+	let kind = format_ident!("{}Kind", ident);
+	let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+	let names = variants.iter().map(|(v, _)| &v.ident).collect::<Vec<_>>();
+	let strs = names.iter().map(|name| name.to_string()).collect::<Vec<_>>();
+	let doc = format!("The fieldless discriminant of [`{}`].", ident);
+
+	quote! {
+		#[doc = #doc]
+		#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, ::wire::__private::serde::Serialize, ::wire::__private::serde::Deserialize)]
+		#[serde(crate = "::wire::__private::serde")]
+		pub enum #kind {
+			#(#names),*
+		}
+
+		impl #kind {
+			/// All kinds, in declaration order.
+			pub const ALL: &'static [#kind] = &[#(#kind::#names),*];
+
+			/// Returns the name of the kind.
+			pub const fn as_str(&self) -> &'static str {
+				match *self {
+					#(#kind::#names => #strs),*
+				}
+			}
+		}
+
+		impl ::core::fmt::Display for #kind {
+			fn fmt(&self, f: &mut ::core::fmt::Formatter<'_>) -> ::core::fmt::Result {
+				f.write_str(self.as_str())
+			}
+		}
+
+		impl ::core::str::FromStr for #kind {
+			type Err = ::wire::ParseKindError;
+
+			fn from_str(s: &str) -> ::core::result::Result<Self, Self::Err> {
+				match s {
+					#(#strs => ::core::result::Result::Ok(#kind::#names),)*
+					_ => ::core::result::Result::Err(::wire::ParseKindError(s.to_string())),
+				}
+			}
+		}
+
+		impl #impl_generics #ident #ty_generics #where_clause {
+			/// Returns the kind of the variant.
+			pub fn kind(&self) -> #kind {
+				match *self {
+					#(#ident::#names { .. } => #kind::#names),*
+				}
+			}
+		}
+	}
+}
+
+/// Generates the `From<Variant> for Enum` and `TryFrom<Enum> for Variant` conversions and the `Variant::KIND` constant.
+///
+/// The `scope` is the path prefix under which the enum and its kind are reachable from the generated struct.
+fn variant_conversions(ident: &syn::Ident, generics: &syn::Generics, v: &syn::Variant, name: &syn::Ident, scope: &proc_macro2::TokenStream) -> proc_macro2::TokenStream {
+	let variant = &v.ident;
+	let kind = format_ident!("{}Kind", ident);
+	let ident = quote! { #scope #ident };
+	let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+	let variant_generics = variant_generics(generics, &v.fields);
+	let (variant_impl_generics, variant_ty_generics, variant_where_clause) = variant_generics.split_for_impl();
+	let (pattern, construct) = match &v.fields {
+		syn::Fields::Named(fields) => {
+			let bindings = fields.named.iter().map(|field| field.ident.clone().unwrap()).collect::<Vec<_>>();
+			(quote! { { #(#bindings),* } }, quote! { { #(#bindings),* } })
+		},
+		syn::Fields::Unnamed(fields) => {
+			let bindings = (0..fields.unnamed.len()).map(|i| format_ident!("__{}", i)).collect::<Vec<_>>();
+			(quote! { ( #(#bindings),* ) }, quote! { ( #(#bindings),* ) })
+		},
+		syn::Fields::Unit => (quote! {}, quote! {}),
+	};
+
+	quote! {
+		impl #impl_generics ::core::convert::From<#name #variant_ty_generics> for #ident #ty_generics #where_clause {
+			fn from(value: #name #variant_ty_generics) -> Self {
+				let #name #pattern = value;
+				#ident::#variant #construct
+			}
+		}
+
+		impl #impl_generics ::core::convert::TryFrom<#ident #ty_generics> for #name #variant_ty_generics #where_clause {
+			type Error = #ident #ty_generics;
+
+			#[allow(unreachable_patterns)]
+			fn try_from(value: #ident #ty_generics) -> ::core::result::Result<Self, Self::Error> {
+				match value {
+					#ident::#variant #pattern => ::core::result::Result::Ok(#name #construct),
+					value => ::core::result::Result::Err(value),
+				}
+			}
+		}
+
+		impl #variant_impl_generics #name #variant_ty_generics #variant_where_clause {
+			/// The kind of the variant.
+			pub const KIND: #scope #kind = #scope #kind::#variant;
+		}
+	}
+}
+
+/// Code generation for the `bevy` integration.
+#[cfg(feature = "bevy")]
+mod bevy {
+	use super::*;
+
+	/// Generates the `Event` impl of a variant struct.
+	pub fn variant_event(generics: &syn::Generics, v: &syn::Variant, name: &syn::Ident) -> proc_macro2::TokenStream {
+		let mut generics = variant_generics(generics, &v.fields);
+		generics.make_where_clause().predicates.push(syn::parse_quote! { Self: Send + Sync + 'static });
+		let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
+		quote! {
+			impl #impl_generics ::wire::__private::bevy_ecs::event::Event for #name #ty_generics #where_clause {
+				type Traversal = ();
+			}
+		}
+	}
+
+	/// Generates the `WireEvents` impl of the enum, dispatching requests as per-variant events.
+	pub fn wire_events(ident: &syn::Ident, generics: &syn::Generics, variants: &[(&syn::Variant, Options)], module: Option<&syn::Ident>) -> proc_macro2::TokenStream {
+		let mut impl_generics_source = generics.clone();
+		let where_clause = impl_generics_source.make_where_clause();
+		where_clause.predicates.push(syn::parse_quote! { Self: Send + Sync + 'static });
+
+		let mut add_events = Vec::new();
+		let mut arms = Vec::new();
+		for (v, options) in variants {
+			let variant = &v.ident;
+			if options.skip {
+				arms.push(quote! {
+					action @ #ident::#variant { .. } => {
+						world.send_event(::wire::Req { from, action, corrid });
+					},
+				});
+				continue
+			}
+
+			let name = options.struct_name(v);
+			let variant_generics = variant_generics(generics, &v.fields);
+			let (_, variant_ty_generics, _) = variant_generics.split_for_impl();
+			let path = match module {
+				Some(module) => quote! { #module::#name #variant_ty_generics },
+				None => quote! { #name #variant_ty_generics },
+			};
+			where_clause.predicates.push(syn::parse_quote! { #path: Send + Sync + 'static });
+			add_events.push(quote! {
+				app.add_event::<::wire::Req<#path>>();
+			});
+			arms.push(quote! {
+				action @ #ident::#variant { .. } => {
+					let action = <#path as ::core::convert::TryFrom<Self>>::try_from(action).unwrap_or_else(|_| unreachable!());
+					world.send_event(::wire::Req { from, action, corrid });
+				},
+			});
+		}
+
+		let (impl_generics, ty_generics, where_clause) = impl_generics_source.split_for_impl();
+		quote! {
+			impl #impl_generics ::wire::bevy::WireEvents for #ident #ty_generics #where_clause {
+				fn add_events(app: &mut ::wire::__private::bevy_app::App) {
+					#(#add_events)*
+				}
+
+				fn send_event(req: ::wire::Req<Self>, world: &mut ::wire::__private::bevy_ecs::world::World) {
+					let ::wire::Req { from, action, corrid } = req;
+					match action {
+						#(#arms)*
+					}
+				}
+			}
+		}
+	}
+}
+
 // todo: switch to virtue once it gets attributes on structs
 //
 // use virtue::{prelude::*, generate::Parent};